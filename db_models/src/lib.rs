@@ -21,6 +21,12 @@ pub struct Product {
     #[sqlx(rename = "sellingprice")]
     pub selling_price: f32,
     pub current_stock: f32,
+    pub restock_level: f32,
+    pub cost_price: f32,
+    pub unit: String,
+    pub restock_quantity: f32,
+    pub supplier: String,
+    pub inventory_type: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -8,7 +8,14 @@ CREATE TABLE products (
     department VARCHAR(100),
     category VARCHAR(100),
     selling_price REAL,
-    current_stock REAL
+    current_stock REAL,
+    restock_level REAL,
+    cost_price REAL,
+    unit VARCHAR(100),
+    restock_quantity REAL,
+    supplier VARCHAR(100),
+    inventory_type VARCHAR(50),
+    shelf_life_days REAL
 );
 
 CREATE TABLE customers (
@@ -0,0 +1,2 @@
+-- Restock threshold synced from the POS so recommendations can skip low-stock items
+ALTER TABLE products ADD COLUMN IF NOT EXISTS restock_level REAL;
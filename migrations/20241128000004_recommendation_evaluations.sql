@@ -0,0 +1,26 @@
+-- One row per training run, so evaluation reports can be tied to the model they describe
+CREATE TABLE IF NOT EXISTS recommendation_model_versions (
+    id SERIAL PRIMARY KEY,
+    trained_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    transaction_count INTEGER NOT NULL,
+    items_cached INTEGER NOT NULL,
+    daypart_count INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS recommendation_evaluations (
+    id SERIAL PRIMARY KEY,
+    model_version_id INTEGER REFERENCES recommendation_model_versions(id),
+    evaluated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    train_start TIMESTAMP,
+    test_start TIMESTAMP NOT NULL,
+    test_end TIMESTAMP NOT NULL,
+    train_baskets INTEGER NOT NULL,
+    test_baskets INTEGER NOT NULL,
+    cases INTEGER NOT NULL,
+    k INTEGER NOT NULL,
+    hit_rate DOUBLE PRECISION NOT NULL,
+    precision_at_k DOUBLE PRECISION NOT NULL,
+    mrr DOUBLE PRECISION NOT NULL,
+    catalog_coverage DOUBLE PRECISION NOT NULL,
+    popularity_bias DOUBLE PRECISION NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Cost of goods per product (cost_price_excl in the POS), used for margin-aware pricing
+ALTER TABLE products ADD COLUMN IF NOT EXISTS cost_price REAL;
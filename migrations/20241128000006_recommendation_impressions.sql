@@ -0,0 +1,15 @@
+-- Recommendations the POS showed on an order, joined later with sales on the same receipt
+CREATE TABLE IF NOT EXISTS recommendation_impressions (
+    id SERIAL PRIMARY KEY,
+    receipt_no INTEGER NOT NULL,
+    source_product_id INTEGER,
+    recommended_product_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    score DOUBLE PRECISION,
+    staff VARCHAR(100),
+    model_version_id INTEGER,
+    shown_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_recommendation_impressions_receipt_no
+    ON recommendation_impressions (receipt_no);
@@ -0,0 +1,31 @@
+-- A/B experiments over recommendation model variants
+CREATE TABLE IF NOT EXISTS recommendation_experiments (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL UNIQUE,
+    unit VARCHAR(20) NOT NULL DEFAULT 'terminal',
+    status VARCHAR(20) NOT NULL DEFAULT 'active',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    stopped_at TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS recommendation_experiment_variants (
+    id SERIAL PRIMARY KEY,
+    experiment_id INTEGER NOT NULL REFERENCES recommendation_experiments(id),
+    name VARCHAR(100) NOT NULL,
+    weight INTEGER NOT NULL DEFAULT 1,
+    strategy VARCHAR(20) NOT NULL DEFAULT 'rules',
+    min_support DOUBLE PRECISION,
+    min_confidence DOUBLE PRECISION
+);
+
+CREATE TABLE IF NOT EXISTS recommendation_experiment_exposures (
+    id SERIAL PRIMARY KEY,
+    experiment_id INTEGER NOT NULL REFERENCES recommendation_experiments(id),
+    variant_id INTEGER NOT NULL REFERENCES recommendation_experiment_variants(id),
+    unit_id VARCHAR(100) NOT NULL,
+    product_id INTEGER,
+    customer_id INTEGER,
+    served_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE recommendation_impressions ADD COLUMN IF NOT EXISTS variant_id INTEGER;
@@ -0,0 +1,2 @@
+-- Selling unit (plate, bottle, kg, ...) from the POS, used for content-based product similarity
+ALTER TABLE products ADD COLUMN IF NOT EXISTS unit VARCHAR(100);
@@ -0,0 +1,3 @@
+-- Reorder quantity and supplier from the POS, used for purchase order suggestions
+ALTER TABLE products ADD COLUMN IF NOT EXISTS restock_quantity REAL;
+ALTER TABLE products ADD COLUMN IF NOT EXISTS supplier VARCHAR(100);
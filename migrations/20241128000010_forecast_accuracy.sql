@@ -0,0 +1,15 @@
+-- Rolling-origin backtest results per product and forecasting method; forecasts use the selected row's method
+CREATE TABLE IF NOT EXISTS forecast_accuracy (
+    product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    method VARCHAR(50) NOT NULL,
+    mape DOUBLE PRECISION,
+    smape DOUBLE PRECISION NOT NULL,
+    mase DOUBLE PRECISION,
+    bias DOUBLE PRECISION NOT NULL,
+    forecasts INTEGER NOT NULL,
+    origins INTEGER NOT NULL,
+    horizon_days INTEGER NOT NULL,
+    selected BOOLEAN NOT NULL DEFAULT FALSE,
+    evaluated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (product_id, method)
+);
@@ -0,0 +1,19 @@
+-- Bills of materials: the ingredients, themselves products, that go into one batch of a dish
+ALTER TABLE products ADD COLUMN IF NOT EXISTS inventory_type VARCHAR(50);
+
+CREATE TABLE IF NOT EXISTS recipes (
+    dish_product_id INTEGER PRIMARY KEY REFERENCES products(product_id) ON DELETE CASCADE,
+    yield_portions REAL NOT NULL DEFAULT 1,
+    notes TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS recipe_ingredients (
+    dish_product_id INTEGER NOT NULL REFERENCES recipes(dish_product_id) ON DELETE CASCADE,
+    ingredient_product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    -- In the ingredient's own unit, per batch
+    quantity REAL NOT NULL,
+    -- Usable share after trimming and cooking losses, 0-1
+    yield_ratio REAL NOT NULL DEFAULT 1,
+    PRIMARY KEY (dish_product_id, ingredient_product_id)
+);
@@ -0,0 +1,21 @@
+-- Forecasts computed by a background job after each sync, so the stock endpoint does not refit per request
+CREATE TABLE IF NOT EXISTS forecast_runs (
+    id SERIAL PRIMARY KEY,
+    generated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    history_end DATE,
+    products INTEGER NOT NULL,
+    horizon_days INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS demand_forecasts (
+    product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    forecast_date DATE NOT NULL,
+    model_version INTEGER NOT NULL REFERENCES forecast_runs(id) ON DELETE CASCADE,
+    method VARCHAR(50) NOT NULL,
+    units DOUBLE PRECISION NOT NULL,
+    lower_80 DOUBLE PRECISION NOT NULL,
+    upper_80 DOUBLE PRECISION NOT NULL,
+    lower_95 DOUBLE PRECISION NOT NULL,
+    upper_95 DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (product_id, forecast_date)
+);
@@ -0,0 +1,21 @@
+-- Stock-out alerts raised after each forecast run: open risk alerts are updated in place and
+-- resolved once stock covers demand again; historical stock-outs are one row per product and day
+CREATE TABLE IF NOT EXISTS stock_alerts (
+    id SERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    alert_type VARCHAR(50) NOT NULL,
+    severity VARCHAR(20) NOT NULL,
+    message TEXT NOT NULL,
+    current_stock DOUBLE PRECISION,
+    daily_demand DOUBLE PRECISION,
+    days_of_cover DOUBLE PRECISION,
+    lead_time_days DOUBLE PRECISION,
+    alert_date DATE,
+    raised_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS stock_alerts_dated
+    ON stock_alerts (product_id, alert_type, alert_date)
+    WHERE alert_date IS NOT NULL;
@@ -0,0 +1,12 @@
+-- User-defined calendar events (gazetted holidays such as Eid, matches, promotions) used as
+-- forecasting regressors alongside the built-in Kenyan public holidays and paydays
+CREATE TABLE IF NOT EXISTS calendar_events (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    -- public_holiday, payday or event
+    event_type VARCHAR(50) NOT NULL DEFAULT 'event',
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (name, start_date)
+);
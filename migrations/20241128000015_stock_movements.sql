@@ -0,0 +1,28 @@
+-- Append-only stock ledger. Sync compares each product's POS inventory fields with the last
+-- row written for it and appends the received, used and adjusted quantities; every row keeps
+-- the POS values it was derived from so the next change can be diffed against them
+CREATE TABLE IF NOT EXISTS stock_movements (
+    id BIGSERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    -- opening, received, used, adjustment or shift_count
+    movement_type VARCHAR(30) NOT NULL,
+    -- Signed change to the stock on hand; zero for shift counts
+    quantity DOUBLE PRECISION NOT NULL,
+    stock_after DOUBLE PRECISION NOT NULL,
+    used_stock DOUBLE PRECISION,
+    additional_stock DOUBLE PRECISION,
+    shift_stock_fed DOUBLE PRECISION,
+    shift_current_stock DOUBLE PRECISION,
+    adjusted_at TIMESTAMP,
+    occurred_at TIMESTAMP NOT NULL,
+    recorded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS stock_movements_product_time
+    ON stock_movements (product_id, occurred_at, id);
+
+-- Opening balances from the stock already synced
+INSERT INTO stock_movements (product_id, movement_type, quantity, stock_after, occurred_at)
+SELECT product_id, 'opening', COALESCE(current_stock, 0), COALESCE(current_stock, 0), CURRENT_TIMESTAMP
+FROM products
+WHERE NOT EXISTS (SELECT 1 FROM stock_movements);
@@ -0,0 +1,18 @@
+-- Shelf life is kept here rather than synced, since the POS has no such field; NULL means
+-- the product does not spoil
+ALTER TABLE products ADD COLUMN IF NOT EXISTS shelf_life_days REAL;
+
+CREATE TABLE IF NOT EXISTS waste_records (
+    id SERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(product_id) ON DELETE CASCADE,
+    quantity DOUBLE PRECISION NOT NULL,
+    -- expired, spoiled, damaged, ...
+    reason VARCHAR(50) NOT NULL DEFAULT 'expired',
+    -- Cost price when the waste was recorded
+    unit_cost DOUBLE PRECISION,
+    wasted_on DATE NOT NULL DEFAULT CURRENT_DATE,
+    notes TEXT,
+    recorded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS waste_records_wasted_on ON waste_records (wasted_on, product_id);
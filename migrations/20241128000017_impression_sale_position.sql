@@ -0,0 +1,3 @@
+-- Newest sale line on the order when the recommendations were shown; only lines after it
+-- count as conversions, so items already ordered are not credited to the recommendation
+ALTER TABLE recommendation_impressions ADD COLUMN IF NOT EXISTS last_sale_id INTEGER;
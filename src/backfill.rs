@@ -43,7 +43,14 @@ async fn create_pg_tables(pg_pool: &PgPool) -> Result<(), sqlx::Error> {
             department VARCHAR(100),
             category VARCHAR(100),
             selling_price REAL,
-            current_stock REAL
+            current_stock REAL,
+            restock_level REAL,
+            cost_price REAL,
+            unit VARCHAR(100),
+            restock_quantity REAL,
+            supplier VARCHAR(100),
+            inventory_type VARCHAR(50),
+            shelf_life_days REAL
         );",
         "CREATE TABLE customers (
             customer_id INTEGER PRIMARY KEY,
@@ -0,0 +1,166 @@
+use crate::errors::RequestError;
+use crate::ml::apriori;
+use crate::recommendations;
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+const MIN_BUNDLE_SIZE: usize = 2;
+const MAX_BUNDLE_SIZE: usize = 4;
+const MAX_DAYS: i64 = 10 * 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BundleOptions {
+    /// Minimum share of receipts that must already contain the whole combo.
+    pub min_support: Option<f64>,
+    /// Gross margin the bundle price should keep, e.g. 0.6 for 60%.
+    pub target_margin: Option<f64>,
+    /// Largest discount off the separate prices, e.g. 0.15 for 15%.
+    pub max_discount: Option<f64>,
+    /// How many days of receipts to mine, counted back from the latest sale.
+    pub days: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleItem {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub selling_price: f32,
+    pub cost_price: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleSuggestion {
+    pub items: Vec<BundleItem>,
+    pub separate_price: f64,
+    pub bundle_price: f64,
+    pub discount_pct: f64,
+    pub total_cost: Option<f64>,
+    pub margin_pct: Option<f64>,
+    pub support: f64,
+    /// Receipts in the window that already bought every item separately.
+    pub covered_receipts: i64,
+    pub covered_receipts_per_week: f64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ProductPricing {
+    product_id: i32,
+    name: Option<String>,
+    selling_price: Option<f32>,
+    cost_price: Option<f32>,
+}
+
+pub async fn get_bundle_suggestions(
+    pool: &PgPool,
+    options: &BundleOptions,
+) -> Result<Vec<BundleSuggestion>> {
+    let min_support = options.min_support.unwrap_or(0.005);
+    let target_margin = options.target_margin.unwrap_or(0.6).clamp(0.0, 0.95);
+    let max_discount = options.max_discount.unwrap_or(0.15).clamp(0.0, 1.0);
+    let days = options.days.unwrap_or(90).max(1);
+    if days > MAX_DAYS {
+        bail!(RequestError::Invalid(format!("days must be at most {}", MAX_DAYS)));
+    }
+    let limit = options.limit.unwrap_or(20);
+
+    let baskets = recommendations::get_transactions(pool).await?;
+    let Some(latest) = baskets.iter().filter_map(|b| b.transaction_date).max() else {
+        return Ok(Vec::new());
+    };
+    let window_start = latest
+        .checked_sub_signed(Duration::days(days))
+        .unwrap_or(NaiveDateTime::MIN);
+    let transactions: Vec<Vec<i32>> = baskets
+        .into_iter()
+        .filter(|b| b.transaction_date.is_some_and(|date| date >= window_start))
+        .map(|b| b.items)
+        .collect();
+    if transactions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let weights = vec![1.0; transactions.len()];
+    let itemsets: Vec<(Vec<i32>, f64)> =
+        apriori::get_frequent_itemsets(&transactions, &weights, min_support)
+            .into_iter()
+            .filter(|(itemset, _)| (MIN_BUNDLE_SIZE..=MAX_BUNDLE_SIZE).contains(&itemset.len()))
+            .collect();
+
+    let mut product_ids: Vec<i32> =
+        itemsets.iter().flat_map(|(itemset, _)| itemset.clone()).collect();
+    product_ids.sort();
+    product_ids.dedup();
+    let pricing: HashMap<i32, ProductPricing> = sqlx::query_as::<_, ProductPricing>(
+        "SELECT product_id, name, selling_price, cost_price FROM products WHERE product_id = ANY($1)",
+    )
+    .bind(&product_ids)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|p| (p.product_id, p))
+    .collect();
+
+    let weeks = days as f64 / 7.0;
+    let mut suggestions: Vec<BundleSuggestion> = itemsets
+        .into_iter()
+        .filter_map(|(itemset, support)| {
+            let items: Vec<BundleItem> = itemset
+                .iter()
+                .map(|id| {
+                    let product = pricing.get(id)?;
+                    Some(BundleItem {
+                        product_id: *id,
+                        name: product.name.clone(),
+                        selling_price: product.selling_price.filter(|p| *p > 0.0)?,
+                        cost_price: product.cost_price.filter(|c| *c > 0.0),
+                    })
+                })
+                .collect::<Option<_>>()?;
+
+            let separate_price: f64 = items.iter().map(|i| i.selling_price as f64).sum();
+            let total_cost: Option<f64> = items
+                .iter()
+                .map(|i| i.cost_price.map(|c| c as f64))
+                .sum();
+            let bundle_price = price_bundle(separate_price, total_cost, target_margin, max_discount);
+            let covered_receipts = (support * transactions.len() as f64).round() as i64;
+
+            Some(BundleSuggestion {
+                discount_pct: (1.0 - bundle_price / separate_price) * 100.0,
+                margin_pct: total_cost.map(|cost| (1.0 - cost / bundle_price) * 100.0),
+                items,
+                separate_price,
+                bundle_price,
+                total_cost,
+                support,
+                covered_receipts,
+                covered_receipts_per_week: covered_receipts as f64 / weeks,
+            })
+        })
+        .collect();
+
+    suggestions.sort_by_key(|s| std::cmp::Reverse(s.covered_receipts));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
+
+// The cheapest price that still keeps the target margin, but never more than
+// the separate prices and never discounted past the cap. Without a known cost
+// the full allowed discount is not given away; half of it is.
+fn price_bundle(
+    separate_price: f64,
+    total_cost: Option<f64>,
+    target_margin: f64,
+    max_discount: f64,
+) -> f64 {
+    let floor = separate_price * (1.0 - max_discount);
+    let price = match total_cost {
+        Some(cost) => (cost / (1.0 - target_margin)).max(floor),
+        None => separate_price * (1.0 - max_discount / 2.0),
+    };
+    price.round().min(separate_price)
+}
@@ -0,0 +1,503 @@
+use crate::errors::RequestError;
+use crate::forecasting;
+use crate::ml::forecasting::WEEK;
+use crate::ml::regressors::{self, DayFeatures, Regressor, Uplift};
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Days, Duration, NaiveDate, NaiveDateTime, Weekday};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashSet};
+use tracing::info;
+
+// Longest user event accepted; longer spans are seasons, not events.
+const MAX_EVENT_DAYS: i64 = 31;
+// Month-end pay lands on the last working day and is spent over the next few.
+const PAYDAY_DAYS: i64 = 3;
+const PAYDAY_NAME: &str = "Month-end payday";
+const DEFAULT_RANGE_DAYS: u64 = 90;
+// Longest range the calendar endpoint lists; built-in days are generated per year.
+const MAX_RANGE_DAYS: i64 = 5 * 366;
+// Easter is only computed for the Gregorian calendar.
+const FIRST_GREGORIAN_YEAR: i32 = 1583;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewEvent {
+    pub name: String,
+    /// `public_holiday`, `payday` or `event` (the default).
+    pub event_type: Option<String>,
+    pub start_date: NaiveDate,
+    /// Last day of the event; a one-day event when omitted.
+    pub end_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CalendarEvent {
+    pub id: i32,
+    pub name: String,
+    pub event_type: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub event_type: Option<String>,
+}
+
+/// One day of one holiday, payday or event.
+#[derive(Debug, Clone, Serialize)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub name: String,
+    pub event_type: Regressor,
+    /// The user-defined event the day comes from; `None` for built-in days.
+    pub event_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpliftQuery {
+    pub product_id: Option<i32>,
+    pub history_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventUplift {
+    pub name: String,
+    pub event_type: Regressor,
+    pub dates: Vec<NaiveDate>,
+    pub actual_units: f64,
+    /// Units the same days would have sold as ordinary days of their weekday.
+    pub baseline_units: f64,
+    pub uplift_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpliftReport {
+    pub product_id: Option<i32>,
+    pub history_start: Option<NaiveDate>,
+    pub history_end: Option<NaiveDate>,
+    /// Effect per kind of day, as the forecasts apply it.
+    pub regressors: Vec<Uplift>,
+    pub events: Vec<EventUplift>,
+}
+
+/// Built-in and user-defined calendar days. Built-in days are generated for
+/// whatever years are asked for.
+#[derive(Debug, Clone, Default)]
+pub struct Calendar {
+    user_days: Vec<CalendarDay>,
+}
+
+impl Calendar {
+    /// Every calendar day from `from` to `to` inclusive, by date.
+    pub fn days(&self, from: NaiveDate, to: NaiveDate) -> Vec<CalendarDay> {
+        let mut days: Vec<CalendarDay> = (from.year()..=to.year())
+            .flat_map(built_in_days)
+            .chain(self.user_days.iter().cloned())
+            .filter(|day| day.date >= from && day.date <= to)
+            .collect();
+        days.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.name.cmp(&b.name)));
+        days
+    }
+
+    /// Regressors of `days` consecutive days from `start`.
+    pub fn features(&self, start: NaiveDate, days: usize) -> Vec<DayFeatures> {
+        let mut features = vec![DayFeatures::default(); days];
+        if days == 0 {
+            return features;
+        }
+        for day in self.days(start, start + Duration::days(days as i64 - 1)) {
+            features[(day.date - start).num_days() as usize].insert(day.event_type);
+        }
+        features
+    }
+}
+
+/// Kenyan public holidays under the Public Holidays Act, with a holiday that
+/// falls on a Sunday observed on the next free day, followed by the paydays.
+/// Islamic holidays depend on the moon sighting and are gazetted each year,
+/// so they are entered as user events.
+pub fn kenyan_public_holidays(year: i32) -> Vec<(NaiveDate, String)> {
+    let date = |month, day| NaiveDate::from_ymd_opt(year, month, day);
+    let easter = easter_sunday(year);
+    let mut holidays: Vec<(NaiveDate, String)> = [
+        (date(1, 1), "New Year's Day"),
+        (easter.and_then(|e| e.checked_sub_days(Days::new(2))), "Good Friday"),
+        (easter.and_then(|e| e.checked_add_days(Days::new(1))), "Easter Monday"),
+        (date(5, 1), "Labour Day"),
+        (date(6, 1), "Madaraka Day"),
+        (date(10, 10), "Mazingira Day"),
+        (date(10, 20), "Mashujaa Day"),
+        (date(12, 12), "Jamhuri Day"),
+        (date(12, 25), "Christmas Day"),
+        (date(12, 26), "Boxing Day"),
+    ]
+    .into_iter()
+    .filter_map(|(date, name)| Some((date?, name.to_string())))
+    .collect();
+
+    let mut taken: HashSet<NaiveDate> = holidays.iter().map(|(date, _)| *date).collect();
+    let mut observed = Vec::new();
+    for (date, name) in &holidays {
+        if date.weekday() != Weekday::Sun {
+            continue;
+        }
+        let mut day = *date + Duration::days(1);
+        while taken.contains(&day) {
+            day += Duration::days(1);
+        }
+        taken.insert(day);
+        observed.push((day, format!("{} (observed)", name)));
+    }
+    holidays.extend(observed);
+    holidays.sort();
+    holidays
+}
+
+/// The last working day of each month, skipping weekends and public holidays.
+pub fn month_end_paydays(year: i32) -> Vec<NaiveDate> {
+    let holidays: HashSet<NaiveDate> = kenyan_public_holidays(year)
+        .into_iter()
+        .map(|(date, _)| date)
+        .collect();
+    (1..=12)
+        .filter_map(|month| {
+            let next_month = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)
+            }?;
+            let mut day = next_month - Duration::days(1);
+            while matches!(day.weekday(), Weekday::Sat | Weekday::Sun) || holidays.contains(&day) {
+                day -= Duration::days(1);
+            }
+            Some(day)
+        })
+        .collect()
+}
+
+fn built_in_days(year: i32) -> Vec<CalendarDay> {
+    let holidays = kenyan_public_holidays(year).into_iter().map(|(date, name)| CalendarDay {
+        date,
+        name,
+        event_type: Regressor::PublicHoliday,
+        event_id: None,
+    });
+    let paydays = month_end_paydays(year).into_iter().flat_map(|payday| {
+        (0..PAYDAY_DAYS).map(move |offset| CalendarDay {
+            date: payday + Duration::days(offset),
+            name: PAYDAY_NAME.to_string(),
+            event_type: Regressor::Payday,
+            event_id: None,
+        })
+    });
+    holidays.chain(paydays).collect()
+}
+
+// Anonymous Gregorian algorithm. `None` before the Gregorian calendar.
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    if year < FIRST_GREGORIAN_YEAR {
+        return None;
+    }
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+pub async fn load_calendar(pool: &PgPool) -> Result<Calendar, sqlx::Error> {
+    let events = get_events(pool).await?;
+    let user_days = events
+        .iter()
+        .flat_map(|event| {
+            let event_type = Regressor::parse(&event.event_type).unwrap_or(Regressor::Event);
+            (0..=(event.end_date - event.start_date).num_days()).map(move |offset| CalendarDay {
+                date: event.start_date + Duration::days(offset),
+                name: event.name.clone(),
+                event_type,
+                event_id: Some(event.id),
+            })
+        })
+        .collect();
+    Ok(Calendar { user_days })
+}
+
+pub async fn get_calendar(pool: &PgPool, query: &CalendarQuery) -> Result<Vec<CalendarDay>> {
+    let (from, to) = query_range(query, chrono::Local::now().date_naive())?;
+    let event_type = match query.event_type.as_deref() {
+        Some(value) => match Regressor::parse(value) {
+            Some(event_type) => Some(event_type),
+            None => bail!(RequestError::Invalid(format!("Unknown event type {}", value))),
+        },
+        None => None,
+    };
+    Ok(load_calendar(pool)
+        .await?
+        .days(from, to)
+        .into_iter()
+        .filter(|day| event_type.is_none_or(|t| day.event_type == t))
+        .collect())
+}
+
+// From today and 90 days on by default.
+fn query_range(query: &CalendarQuery, today: NaiveDate) -> Result<(NaiveDate, NaiveDate)> {
+    let from = query.from.unwrap_or(today);
+    let to = query
+        .to
+        .unwrap_or_else(|| from.checked_add_days(Days::new(DEFAULT_RANGE_DAYS)).unwrap_or(NaiveDate::MAX));
+    if to < from {
+        bail!(RequestError::Invalid("to must not be before from".to_string()));
+    }
+    if (to - from).num_days() > MAX_RANGE_DAYS {
+        bail!(RequestError::Invalid(format!(
+            "The range must not be longer than {} days",
+            MAX_RANGE_DAYS
+        )));
+    }
+    Ok((from, to))
+}
+
+pub async fn get_events(pool: &PgPool) -> Result<Vec<CalendarEvent>, sqlx::Error> {
+    sqlx::query_as::<_, CalendarEvent>(
+        "SELECT id, name, event_type, start_date, end_date, created_at
+         FROM calendar_events
+         ORDER BY start_date, name",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Adds events, replacing any with the same name and start date.
+pub async fn save_events(pool: &PgPool, events: &[NewEvent]) -> Result<Vec<CalendarEvent>> {
+    for event in events {
+        if event.name.trim().is_empty() {
+            bail!(RequestError::Invalid("Event name must not be empty".to_string()));
+        }
+        if let Some(event_type) = &event.event_type {
+            if Regressor::parse(event_type).is_none() {
+                bail!(RequestError::Invalid(format!(
+                    "Unknown event type {} for {}",
+                    event_type, event.name
+                )));
+            }
+        }
+        let end = event.end_date.unwrap_or(event.start_date);
+        if end < event.start_date {
+            bail!(RequestError::Invalid(format!("{} ends before it starts", event.name)));
+        }
+        if (end - event.start_date).num_days() >= MAX_EVENT_DAYS {
+            bail!(RequestError::Invalid(format!(
+                "{} is longer than {} days",
+                event.name, MAX_EVENT_DAYS
+            )));
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let mut saved = Vec::with_capacity(events.len());
+    for event in events {
+        let event_type = event
+            .event_type
+            .as_deref()
+            .and_then(Regressor::parse)
+            .unwrap_or(Regressor::Event);
+        saved.push(
+            sqlx::query_as::<_, CalendarEvent>(
+                r#"
+                INSERT INTO calendar_events (name, event_type, start_date, end_date)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (name, start_date) DO UPDATE
+                SET event_type = EXCLUDED.event_type, end_date = EXCLUDED.end_date
+                RETURNING id, name, event_type, start_date, end_date, created_at
+                "#,
+            )
+            .bind(event.name.trim())
+            .bind(event_type.as_str())
+            .bind(event.start_date)
+            .bind(event.end_date.unwrap_or(event.start_date))
+            .fetch_one(&mut *tx)
+            .await?,
+        );
+    }
+    tx.commit().await?;
+    Ok(saved)
+}
+
+pub async fn delete_event(pool: &PgPool, id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM calendar_events WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Loads events from a JSON file holding a list of events, as posted to the API.
+pub async fn load_events_file(pool: &PgPool, path: &str) -> Result<usize> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read calendar events file {}", path))?;
+    let events: Vec<NewEvent> = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse calendar events file {}", path))?;
+    let saved = save_events(pool, &events).await?;
+    info!("Loaded {} calendar events from {}.", saved.len(), path);
+    Ok(saved.len())
+}
+
+/// How much more (or less) sold on each holiday, payday and event in the
+/// history than on ordinary days of the same weekday around it, for one
+/// product or the whole store.
+pub async fn get_uplift_report(pool: &PgPool, query: &UpliftQuery) -> Result<UpliftReport> {
+    let history_days = query.history_days.unwrap_or(365);
+    let series = forecasting::get_demand_series(pool, Some(history_days), query.product_id).await?;
+    let calendar = load_calendar(pool).await?;
+
+    let (Some(start), Some(end)) = (
+        series.values().map(|s| s.start).min(),
+        series.values().map(|s| s.end()).max(),
+    ) else {
+        return Ok(UpliftReport {
+            product_id: query.product_id,
+            history_start: None,
+            history_end: None,
+            regressors: Vec::new(),
+            events: Vec::new(),
+        });
+    };
+    let mut units = vec![0.0; (end - start).num_days() as usize + 1];
+    for series in series.values() {
+        let offset = (series.start - start).num_days() as usize;
+        for (day, sold) in series.daily.iter().enumerate() {
+            units[offset + day] += sold;
+        }
+    }
+    let features = calendar.features(start, units.len());
+
+    let mut events: BTreeMap<(String, &'static str), EventUplift> = BTreeMap::new();
+    for day in calendar.days(start, end) {
+        let t = (day.date - start).num_days() as usize;
+        let Some(base) = regressors::baseline(&units, &features, t, WEEK) else {
+            continue;
+        };
+        let event = events
+            .entry((day.name.clone(), day.event_type.as_str()))
+            .or_insert_with(|| EventUplift {
+                name: day.name.clone(),
+                event_type: day.event_type,
+                dates: Vec::new(),
+                actual_units: 0.0,
+                baseline_units: 0.0,
+                uplift_pct: None,
+            });
+        event.dates.push(day.date);
+        event.actual_units += units[t];
+        event.baseline_units += base;
+    }
+    let mut events: Vec<EventUplift> = events
+        .into_values()
+        .map(|mut event| {
+            event.uplift_pct = (event.baseline_units > 0.0)
+                .then(|| 100.0 * (event.actual_units / event.baseline_units - 1.0));
+            event
+        })
+        .collect();
+    events.sort_by(|a, b| {
+        b.uplift_pct
+            .unwrap_or(f64::NEG_INFINITY)
+            .total_cmp(&a.uplift_pct.unwrap_or(f64::NEG_INFINITY))
+    });
+
+    Ok(UpliftReport {
+        product_id: query.product_id,
+        history_start: Some(start),
+        history_end: Some(end),
+        regressors: regressors::fit_uplifts(&units, &features, WEEK),
+        events,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn holiday(year: i32, name: &str) -> Option<NaiveDate> {
+        kenyan_public_holidays(year)
+            .into_iter()
+            .find(|(_, holiday)| holiday == name)
+            .map(|(date, _)| date)
+    }
+
+    #[test]
+    fn easter_matches_known_dates() {
+        assert_eq!(easter_sunday(2024), Some(date(2024, 3, 31)));
+        assert_eq!(easter_sunday(2025), Some(date(2025, 4, 20)));
+        assert_eq!(easter_sunday(2000), Some(date(2000, 4, 23)));
+        assert_eq!(easter_sunday(1000), None);
+        assert_eq!(easter_sunday(-5), None);
+        assert_eq!(holiday(2024, "Good Friday"), Some(date(2024, 3, 29)));
+        assert_eq!(holiday(2025, "Easter Monday"), Some(date(2025, 4, 21)));
+    }
+
+    #[test]
+    fn sunday_holiday_is_observed_on_the_next_free_day() {
+        // Jamhuri Day 2021 fell on a Sunday.
+        assert_eq!(holiday(2021, "Jamhuri Day (observed)"), Some(date(2021, 12, 13)));
+        // Christmas 2022 fell on a Sunday and Boxing Day takes the Monday.
+        assert_eq!(holiday(2022, "Christmas Day (observed)"), Some(date(2022, 12, 27)));
+        assert_eq!(holiday(2024, "Jamhuri Day (observed)"), None);
+    }
+
+    #[test]
+    fn paydays_skip_weekends_and_holidays() {
+        let paydays = month_end_paydays(2024);
+        assert_eq!(paydays.len(), 12);
+        // 31 August 2024 was a Saturday.
+        assert_eq!(paydays[7], date(2024, 8, 30));
+        // Boxing Day is a holiday but 31 December 2024 was a Tuesday.
+        assert_eq!(paydays[11], date(2024, 12, 31));
+    }
+
+    #[test]
+    fn extreme_years_do_not_panic() {
+        assert!(!kenyan_public_holidays(NaiveDate::MAX.year()).is_empty());
+        assert!(!kenyan_public_holidays(NaiveDate::MIN.year()).is_empty());
+        assert_eq!(month_end_paydays(NaiveDate::MAX.year()).len(), 11);
+    }
+
+    #[test]
+    fn query_range_is_checked() {
+        let query = |from: Option<NaiveDate>, to: Option<NaiveDate>| CalendarQuery {
+            from,
+            to,
+            event_type: None,
+        };
+        let today = date(2024, 6, 1);
+        assert_eq!(
+            query_range(&query(None, None), today).unwrap(),
+            (today, date(2024, 8, 30))
+        );
+        assert_eq!(
+            query_range(&query(Some(NaiveDate::MAX), None), today).unwrap(),
+            (NaiveDate::MAX, NaiveDate::MAX)
+        );
+        assert!(query_range(&query(Some(today), Some(date(2024, 5, 31))), today).is_err());
+        assert!(query_range(&query(Some(NaiveDate::MIN), Some(NaiveDate::MAX)), today).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use dotenv::dotenv;
 use sqlx::postgres::PgConnectOptions;
-use sqlx::{ConnectOptions, Connection};
+use sqlx::Connection;
 use std::env;
 
 #[tokio::main]
@@ -0,0 +1,145 @@
+use chrono::{Datelike, NaiveDateTime, Timelike, Weekday};
+use std::env;
+use tracing::warn;
+
+const DEFAULT_DAYPARTS: &str = "breakfast:5-11,lunch:11-16,dinner:16-5";
+
+#[derive(Debug, Clone)]
+pub struct DaypartWindow {
+    pub name: String,
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl DaypartWindow {
+    // Windows are [start, end) and may wrap past midnight, e.g. dinner:16-5;
+    // an end of 24 is midnight, so 0-24 covers the whole day.
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            hour >= self.start_hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DaypartConfig {
+    pub windows: Vec<DaypartWindow>,
+    pub split_weekends: bool,
+}
+
+impl DaypartConfig {
+    /// Reads `RECOMMENDATION_DAYPARTS` (e.g. `breakfast:5-11,lunch:11-16,dinner:16-5`)
+    /// and `RECOMMENDATION_SPLIT_WEEKENDS` (defaults to true).
+    pub fn from_env() -> Self {
+        let spec = env::var("RECOMMENDATION_DAYPARTS").unwrap_or_else(|_| DEFAULT_DAYPARTS.into());
+        let windows = match parse_windows(&spec) {
+            Some(windows) => windows,
+            None => {
+                warn!("Invalid RECOMMENDATION_DAYPARTS '{}', using defaults.", spec);
+                parse_windows(DEFAULT_DAYPARTS).unwrap_or_default()
+            }
+        };
+        let split_weekends = env::var("RECOMMENDATION_SPLIT_WEEKENDS")
+            .map(|v| !matches!(v.trim().to_lowercase().as_str(), "0" | "false" | "no"))
+            .unwrap_or(true);
+
+        DaypartConfig {
+            windows,
+            split_weekends,
+        }
+    }
+
+    /// Every segment key a model can be trained for.
+    pub fn segments(&self) -> Vec<String> {
+        self.windows
+            .iter()
+            .flat_map(|w| {
+                if self.split_weekends {
+                    vec![format!("weekday_{}", w.name), format!("weekend_{}", w.name)]
+                } else {
+                    vec![w.name.clone()]
+                }
+            })
+            .collect()
+    }
+
+    /// The segment a transaction at `at` falls into, if any window covers its hour.
+    pub fn segment_for(&self, at: NaiveDateTime) -> Option<String> {
+        let window = self.windows.iter().find(|w| w.contains(at.hour()))?;
+        Some(self.segment_key(&window.name, at))
+    }
+
+    /// Resolves an explicit `daypart` request parameter, falling back to the
+    /// request time. A bare window name (`dinner`) picks up the weekday/weekend
+    /// half from `now`; a full segment key (`weekend_dinner`) is used as is.
+    pub fn resolve(&self, daypart: Option<&str>, now: NaiveDateTime) -> Option<String> {
+        match daypart {
+            Some(requested) => {
+                let requested = requested.trim().to_lowercase();
+                if self.segments().contains(&requested) {
+                    Some(requested)
+                } else if self.windows.iter().any(|w| w.name == requested) {
+                    Some(self.segment_key(&requested, now))
+                } else {
+                    None
+                }
+            }
+            None => self.segment_for(now),
+        }
+    }
+
+    fn segment_key(&self, window: &str, at: NaiveDateTime) -> String {
+        if !self.split_weekends {
+            return window.to_string();
+        }
+        match at.weekday() {
+            Weekday::Sat | Weekday::Sun => format!("weekend_{}", window),
+            _ => format!("weekday_{}", window),
+        }
+    }
+}
+
+fn parse_windows(spec: &str) -> Option<Vec<DaypartWindow>> {
+    spec.split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let (name, hours) = part.trim().split_once(':')?;
+            let (start, end) = hours.split_once('-')?;
+            let start_hour: u32 = start.trim().parse().ok()?;
+            let end_hour: u32 = end.trim().parse().ok()?;
+            if start_hour > 23 || end_hour > 24 || start_hour == end_hour {
+                return None;
+            }
+            Some(DaypartWindow {
+                name: name.trim().to_lowercase(),
+                start_hour,
+                end_hour,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_may_end_at_midnight() {
+        let windows = parse_windows("all:0-24").unwrap();
+        assert!((0..24).all(|hour| windows[0].contains(hour)));
+
+        let windows = parse_windows("late:16-24, night:20-5").unwrap();
+        assert!(windows[0].contains(23) && !windows[0].contains(0));
+        assert!(windows[1].contains(23) && windows[1].contains(4) && !windows[1].contains(5));
+    }
+
+    #[test]
+    fn empty_and_out_of_range_windows_are_rejected() {
+        assert!(parse_windows("none:5-5").is_none());
+        assert!(parse_windows("none:24-24").is_none());
+        assert!(parse_windows("bad:0-25").is_none());
+        assert!(parse_windows("bad:24-3").is_none());
+    }
+}
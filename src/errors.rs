@@ -0,0 +1,21 @@
+use std::fmt;
+
+/// An error caused by the request rather than the server, safe to show the
+/// caller. Anything else stays an internal error.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The request is malformed or fails validation.
+    Invalid(String),
+    /// Something the request refers to does not exist.
+    NotFound(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Invalid(message) | RequestError::NotFound(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
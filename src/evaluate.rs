@@ -0,0 +1,44 @@
+use dotenv::dotenv;
+use sqlx::PgPool;
+use std::env;
+
+use ai_backend::evaluation::{self, EvaluationOptions};
+use ai_backend::recommendations::RecommendationConfig;
+
+// Usage: cargo run --bin evaluate -- [--k 10] [--test-days 14] [--save <model_version_id>]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let mut options = EvaluationOptions::default();
+    let mut save_as: Option<i32> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--k" => options.k = args.next().ok_or("--k needs a value")?.parse()?,
+            "--test-days" => {
+                options.test_days = args.next().ok_or("--test-days needs a value")?.parse()?
+            }
+            "--save" => save_as = Some(args.next().ok_or("--save needs a model version")?.parse()?),
+            other => return Err(format!("Unknown argument: {}", other).into()),
+        }
+    }
+
+    let pool = PgPool::connect(&env::var("DATABASE_URL")?).await?;
+    let config = RecommendationConfig::from_env();
+
+    println!(
+        "Evaluating recommendations: k = {}, holding out the last {} days...",
+        options.k, options.test_days
+    );
+    let report = evaluation::evaluate_recommendations(&pool, &config, &options).await?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if let Some(version) = save_as {
+        evaluation::store_report(&pool, Some(version), &report).await?;
+        println!("Report saved for model version {}.", version);
+    }
+
+    Ok(())
+}
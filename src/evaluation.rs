@@ -0,0 +1,182 @@
+use crate::ml::evaluation::{self, HoldoutCase, Metrics};
+use crate::recommendations::{self, RecommendationConfig};
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use sqlx::PgPool;
+
+#[derive(Debug, Clone)]
+pub struct EvaluationOptions {
+    pub k: usize,
+    pub test_days: i64,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        EvaluationOptions {
+            k: 10,
+            test_days: 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvaluationReport {
+    pub train_start: Option<NaiveDateTime>,
+    pub test_start: NaiveDateTime,
+    pub test_end: NaiveDateTime,
+    pub train_baskets: usize,
+    pub test_baskets: usize,
+    pub metrics: Metrics,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StoredEvaluation {
+    pub id: i32,
+    pub model_version_id: Option<i32>,
+    pub trained_at: Option<NaiveDateTime>,
+    pub evaluated_at: NaiveDateTime,
+    pub train_start: Option<NaiveDateTime>,
+    pub test_start: NaiveDateTime,
+    pub test_end: NaiveDateTime,
+    pub train_baskets: i32,
+    pub test_baskets: i32,
+    pub cases: i32,
+    pub k: i32,
+    pub hit_rate: f64,
+    pub precision_at_k: f64,
+    pub mrr: f64,
+    pub catalog_coverage: f64,
+    pub popularity_bias: f64,
+}
+
+/// Trains on every basket before the last `test_days` of data and scores the
+/// rules on the baskets after it, hiding one item at a time.
+pub async fn evaluate_recommendations(
+    pool: &PgPool,
+    config: &RecommendationConfig,
+    options: &EvaluationOptions,
+) -> Result<EvaluationReport> {
+    let mut baskets: Vec<_> = recommendations::get_transactions(pool)
+        .await?
+        .into_iter()
+        .filter(|b| b.transaction_date.is_some())
+        .collect();
+    baskets.sort_by_key(|b| b.transaction_date);
+
+    let (Some(first), Some(last)) = (
+        baskets.first().and_then(|b| b.transaction_date),
+        baskets.last().and_then(|b| b.transaction_date),
+    ) else {
+        bail!("No dated transactions available for evaluation");
+    };
+    let test_start = last - Duration::days(options.test_days);
+
+    let split = baskets.partition_point(|b| b.transaction_date < Some(test_start));
+    let (train, test) = baskets.split_at(split);
+    if train.is_empty() || test.is_empty() {
+        bail!(
+            "Not enough history to hold out {} days ({} train / {} test baskets)",
+            options.test_days,
+            train.len(),
+            test.len()
+        );
+    }
+
+    let products = recommendations::get_product_catalog(pool).await?;
+    let counts = recommendations::BasketCounts::from_baskets(train, config);
+    let model = recommendations::build_rule_model(train, &counts, &products, config);
+
+    let cases: Vec<HoldoutCase<i32>> = test
+        .iter()
+        .flat_map(|basket| {
+            let segment = basket
+                .transaction_date
+                .and_then(|date| config.dayparts.segment_for(date));
+            evaluation::leave_one_out(&basket.items, segment)
+        })
+        .collect();
+
+    let train_items: Vec<Vec<i32>> = train.iter().map(|b| b.items.clone()).collect();
+    let metrics = evaluation::evaluate(&train_items, &cases, options.k, |case| {
+        recommendations::recommend_for_basket(
+            &model,
+            &case.context,
+            case.segment.as_deref(),
+            options.k,
+        )
+        .into_iter()
+        .map(|r| r.product_id)
+        .collect()
+    });
+
+    Ok(EvaluationReport {
+        train_start: Some(first),
+        test_start,
+        test_end: last,
+        train_baskets: train.len(),
+        test_baskets: test.len(),
+        metrics,
+    })
+}
+
+pub async fn store_report(
+    pool: &PgPool,
+    model_version_id: Option<i32>,
+    report: &EvaluationReport,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO recommendation_evaluations
+            (model_version_id, train_start, test_start, test_end, train_baskets, test_baskets,
+             cases, k, hit_rate, precision_at_k, mrr, catalog_coverage, popularity_bias)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        "#,
+    )
+    .bind(model_version_id)
+    .bind(report.train_start)
+    .bind(report.test_start)
+    .bind(report.test_end)
+    .bind(report.train_baskets as i32)
+    .bind(report.test_baskets as i32)
+    .bind(report.metrics.cases as i32)
+    .bind(report.metrics.k as i32)
+    .bind(report.metrics.hit_rate)
+    .bind(report.metrics.precision_at_k)
+    .bind(report.metrics.mrr)
+    .bind(report.metrics.catalog_coverage)
+    .bind(report.metrics.popularity_bias)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Evaluates the current configuration and files the report under `model_version_id`.
+pub async fn evaluate_model_version(
+    pool: &PgPool,
+    config: &RecommendationConfig,
+    model_version_id: Option<i32>,
+) -> Result<EvaluationReport> {
+    let report = evaluate_recommendations(pool, config, &EvaluationOptions::default()).await?;
+    store_report(pool, model_version_id, &report).await?;
+    Ok(report)
+}
+
+pub async fn get_evaluations(pool: &PgPool) -> Result<Vec<StoredEvaluation>> {
+    let evaluations = sqlx::query_as::<_, StoredEvaluation>(
+        r#"
+        SELECT e.id, e.model_version_id, v.trained_at, e.evaluated_at, e.train_start,
+               e.test_start, e.test_end, e.train_baskets, e.test_baskets, e.cases, e.k,
+               e.hit_rate, e.precision_at_k, e.mrr, e.catalog_coverage, e.popularity_bias
+        FROM recommendation_evaluations e
+        LEFT JOIN recommendation_model_versions v ON e.model_version_id = v.id
+        ORDER BY e.evaluated_at DESC
+        LIMIT 50
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(evaluations)
+}
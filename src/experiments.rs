@@ -0,0 +1,422 @@
+use crate::errors::RequestError;
+use crate::feedback::AFTER_SHOWN;
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+// Variants converting differently at p below this are reported as significant.
+const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExperimentUnit {
+    Terminal,
+    Customer,
+}
+
+impl ExperimentUnit {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExperimentUnit::Terminal => "terminal",
+            ExperimentUnit::Customer => "customer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Experiment {
+    pub id: i32,
+    pub name: String,
+    pub unit: String,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub stopped_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Variant {
+    pub id: i32,
+    pub experiment_id: i32,
+    pub name: String,
+    pub weight: i32,
+    /// `rules` or `personalized`; only the customer endpoint tells them apart.
+    /// There is no FP-Growth strategy: it mines the same itemsets as apriori,
+    /// so variants could only differ in training time, not in what is served.
+    pub strategy: String,
+    pub min_support: Option<f64>,
+    pub min_confidence: Option<f64>,
+}
+
+impl Variant {
+    pub fn is_personalized(&self) -> bool {
+        self.strategy == "personalized"
+    }
+
+    pub fn overrides_thresholds(&self) -> bool {
+        self.min_support.is_some() || self.min_confidence.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentWithVariants {
+    #[serde(flatten)]
+    pub experiment: Experiment,
+    pub variants: Vec<Variant>,
+}
+
+impl ExperimentWithVariants {
+    /// Deterministically buckets `unit_id` by variant weight, so a terminal or
+    /// customer always sees the same variant for the life of the experiment.
+    pub fn assign(&self, unit_id: &str) -> Option<&Variant> {
+        let total: u64 = self.variants.iter().map(|v| v.weight.max(0) as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut bucket = stable_hash(&format!("{}:{}", self.experiment.name, unit_id)) % total;
+        for variant in &self.variants {
+            let weight = variant.weight.max(0) as u64;
+            if bucket < weight {
+                return Some(variant);
+            }
+            bucket -= weight;
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub experiment_id: i32,
+    pub variant_id: i32,
+    pub unit_id: String,
+}
+
+/// Picks the oldest active experiment running on `unit` and assigns `unit_id` to one of its variants.
+pub fn assign<'a>(
+    experiments: &'a [ExperimentWithVariants],
+    unit: ExperimentUnit,
+    unit_id: &str,
+) -> Option<(Assignment, &'a Variant)> {
+    experiments
+        .iter()
+        .filter(|e| e.experiment.status == "active" && e.experiment.unit == unit.as_str())
+        .find_map(|e| {
+            let variant = e.assign(unit_id)?;
+            Some((
+                Assignment {
+                    experiment_id: e.experiment.id,
+                    variant_id: variant.id,
+                    unit_id: unit_id.to_string(),
+                },
+                variant,
+            ))
+        })
+}
+
+// FNV-1a; std's hasher is not guaranteed to be stable across releases.
+fn stable_hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewVariant {
+    pub name: String,
+    pub weight: Option<i32>,
+    pub strategy: Option<String>,
+    pub min_support: Option<f64>,
+    pub min_confidence: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewExperiment {
+    pub name: String,
+    pub unit: Option<ExperimentUnit>,
+    pub variants: Vec<NewVariant>,
+}
+
+pub async fn create_experiment(
+    pool: &PgPool,
+    new: &NewExperiment,
+) -> Result<ExperimentWithVariants> {
+    if new.variants.len() < 2 {
+        bail!(RequestError::Invalid(
+            "An experiment needs at least two variants".to_string()
+        ));
+    }
+    for variant in &new.variants {
+        if variant.weight.is_some_and(|w| w <= 0) {
+            bail!(RequestError::Invalid(format!(
+                "Variant '{}' must have a positive weight",
+                variant.name
+            )));
+        }
+        if let Some(strategy) = variant.strategy.as_deref() {
+            if strategy != "rules" && strategy != "personalized" {
+                bail!(RequestError::Invalid(format!(
+                    "Unknown strategy '{}' for variant '{}'",
+                    strategy, variant.name
+                )));
+            }
+        }
+        for (field, value) in [
+            ("min_support", variant.min_support),
+            ("min_confidence", variant.min_confidence),
+        ] {
+            if value.is_some_and(|v| !(v > 0.0 && v <= 1.0)) {
+                bail!(RequestError::Invalid(format!(
+                    "{} for variant '{}' must be in (0, 1]",
+                    field, variant.name
+                )));
+            }
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let experiment = sqlx::query_as::<_, Experiment>(
+        "INSERT INTO recommendation_experiments (name, unit)
+         VALUES ($1, $2)
+         RETURNING id, name, unit, status, created_at, stopped_at",
+    )
+    .bind(&new.name)
+    .bind(new.unit.unwrap_or(ExperimentUnit::Terminal).as_str())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => anyhow!(RequestError::Invalid(format!(
+            "An experiment named '{}' already exists",
+            new.name
+        ))),
+        _ => e.into(),
+    })?;
+
+    let mut variants = Vec::new();
+    for variant in &new.variants {
+        let variant = sqlx::query_as::<_, Variant>(
+            "INSERT INTO recommendation_experiment_variants
+                (experiment_id, name, weight, strategy, min_support, min_confidence)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING id, experiment_id, name, weight, strategy, min_support, min_confidence",
+        )
+        .bind(experiment.id)
+        .bind(&variant.name)
+        .bind(variant.weight.unwrap_or(1))
+        .bind(variant.strategy.as_deref().unwrap_or("rules"))
+        .bind(variant.min_support)
+        .bind(variant.min_confidence)
+        .fetch_one(&mut *tx)
+        .await?;
+        variants.push(variant);
+    }
+    tx.commit().await?;
+
+    Ok(ExperimentWithVariants {
+        experiment,
+        variants,
+    })
+}
+
+pub async fn stop_experiment(pool: &PgPool, experiment_id: i32) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE recommendation_experiments
+         SET status = 'stopped', stopped_at = NOW()
+         WHERE id = $1 AND status = 'active'",
+    )
+    .bind(experiment_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_experiments(pool: &PgPool, active_only: bool) -> Result<Vec<ExperimentWithVariants>> {
+    let experiments = sqlx::query_as::<_, Experiment>(
+        "SELECT id, name, unit, status, created_at, stopped_at
+         FROM recommendation_experiments
+         WHERE status = 'active' OR NOT $1
+         ORDER BY id",
+    )
+    .bind(active_only)
+    .fetch_all(pool)
+    .await?;
+
+    let ids: Vec<i32> = experiments.iter().map(|e| e.id).collect();
+    let variants = sqlx::query_as::<_, Variant>(
+        "SELECT id, experiment_id, name, weight, strategy, min_support, min_confidence
+         FROM recommendation_experiment_variants
+         WHERE experiment_id = ANY($1)
+         ORDER BY id",
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(experiments
+        .into_iter()
+        .map(|experiment| ExperimentWithVariants {
+            variants: variants
+                .iter()
+                .filter(|v| v.experiment_id == experiment.id)
+                .cloned()
+                .collect(),
+            experiment,
+        })
+        .collect())
+}
+
+pub async fn log_exposure(
+    pool: &PgPool,
+    assignment: &Assignment,
+    product_id: Option<i32>,
+    customer_id: Option<i32>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO recommendation_experiment_exposures
+            (experiment_id, variant_id, unit_id, product_id, customer_id)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(assignment.experiment_id)
+    .bind(assignment.variant_id)
+    .bind(&assignment.unit_id)
+    .bind(product_id)
+    .bind(customer_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct VariantResult {
+    pub variant_id: i32,
+    pub name: String,
+    pub exposures: i64,
+    pub impressions: i64,
+    pub conversions: i64,
+    #[sqlx(skip)]
+    pub is_control: bool,
+    #[sqlx(skip)]
+    pub acceptance_rate: f64,
+    #[sqlx(skip)]
+    pub lift: Option<f64>,
+    #[sqlx(skip)]
+    pub z_score: Option<f64>,
+    #[sqlx(skip)]
+    pub p_value: Option<f64>,
+    #[sqlx(skip)]
+    pub significant: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentResults {
+    pub experiment: Experiment,
+    pub variants: Vec<VariantResult>,
+}
+
+/// Acceptance rate per variant, each compared to the first (control) variant
+/// with a two-proportion z-test.
+pub async fn get_experiment_results(
+    pool: &PgPool,
+    experiment_id: i32,
+) -> Result<Option<ExperimentResults>> {
+    let Some(experiment) = sqlx::query_as::<_, Experiment>(
+        "SELECT id, name, unit, status, created_at, stopped_at
+         FROM recommendation_experiments WHERE id = $1",
+    )
+    .bind(experiment_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let query = format!(
+        r#"
+        SELECT v.id AS variant_id, v.name,
+               (SELECT COUNT(*) FROM recommendation_experiment_exposures e
+                WHERE e.variant_id = v.id) AS exposures,
+               COUNT(r.receipt_id) AS impressions,
+               COUNT(r.receipt_id) FILTER (WHERE EXISTS (
+                   SELECT 1 FROM sales s
+                   WHERE s.receipt_id = r.receipt_id
+                     AND s.product_id = i.recommended_product_id
+                     AND s.sale_id > {AFTER_SHOWN}
+               )) AS conversions
+        FROM recommendation_experiment_variants v
+        LEFT JOIN recommendation_impressions i ON i.variant_id = v.id
+        LEFT JOIN receipts r ON r.receipt_no = i.receipt_no
+        WHERE v.experiment_id = $1
+        GROUP BY v.id, v.name
+        ORDER BY v.id
+        "#
+    );
+    let mut variants = sqlx::query_as::<_, VariantResult>(&query)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let control = variants
+        .first()
+        .map(|v| (v.conversions, v.impressions))
+        .unwrap_or((0, 0));
+    for (index, variant) in variants.iter_mut().enumerate() {
+        variant.acceptance_rate = variant.conversions as f64 / variant.impressions.max(1) as f64;
+        variant.is_control = index == 0;
+        if index == 0 {
+            continue;
+        }
+        let control_rate = control.0 as f64 / control.1.max(1) as f64;
+        if control_rate > 0.0 {
+            variant.lift = Some(variant.acceptance_rate / control_rate - 1.0);
+        }
+        if let Some((z, p)) =
+            two_proportion_z_test(control.0, control.1, variant.conversions, variant.impressions)
+        {
+            variant.z_score = Some(z);
+            variant.p_value = Some(p);
+            variant.significant = p < SIGNIFICANCE_LEVEL;
+        }
+    }
+
+    Ok(Some(ExperimentResults {
+        experiment,
+        variants,
+    }))
+}
+
+// Returns (z, two-sided p-value), or None when either arm has no data.
+fn two_proportion_z_test(
+    conversions_a: i64,
+    trials_a: i64,
+    conversions_b: i64,
+    trials_b: i64,
+) -> Option<(f64, f64)> {
+    if trials_a == 0 || trials_b == 0 {
+        return None;
+    }
+    let (n_a, n_b) = (trials_a as f64, trials_b as f64);
+    let (p_a, p_b) = (conversions_a as f64 / n_a, conversions_b as f64 / n_b);
+    let pooled = (conversions_a + conversions_b) as f64 / (n_a + n_b);
+    let standard_error = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if standard_error == 0.0 {
+        return None;
+    }
+    let z = (p_b - p_a) / standard_error;
+    let p_value = 2.0 * (1.0 - normal_cdf(z.abs()));
+    Some((z, p_value))
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz & Stegun 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
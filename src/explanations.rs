@@ -0,0 +1,146 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplanationSource {
+    #[default]
+    AssociationRule,
+    CategoryRule,
+    DepartmentRule,
+    ContentBased,
+    Collaborative,
+}
+
+/// Where a recommendation came from, in numbers a manager can check against
+/// the receipts. Counts are plain receipt counts, before any time decay.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Explanation {
+    pub source: ExplanationSource,
+    pub summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub antecedent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daypart: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipts_together: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub antecedent_receipts: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_receipts: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lift: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f64>,
+    pub model_version: Option<i32>,
+    pub trained_from: Option<NaiveDateTime>,
+    pub trained_to: Option<NaiveDateTime>,
+}
+
+/// Receipt counts behind a rule.
+#[derive(Debug, Clone, Copy)]
+pub struct RuleCounts {
+    pub together: usize,
+    pub antecedent: usize,
+    pub total: usize,
+}
+
+impl Explanation {
+    pub fn rule(
+        antecedent: i32,
+        counts: RuleCounts,
+        confidence: f64,
+        lift: f64,
+        daypart: Option<&str>,
+    ) -> Self {
+        let during = daypart.map(|d| format!(" ({})", d)).unwrap_or_default();
+        Explanation {
+            source: ExplanationSource::AssociationRule,
+            summary: format!(
+                "Appeared together with product {} in {} of {} receipts{}; {:.0}% of receipts with product {} had it, {:.1}x more often than average.",
+                antecedent,
+                thousands(counts.together),
+                thousands(counts.total),
+                during,
+                confidence * 100.0,
+                antecedent,
+                lift
+            ),
+            antecedent: Some(antecedent.to_string()),
+            daypart: daypart.map(str::to_string),
+            receipts_together: Some(counts.together),
+            antecedent_receipts: Some(counts.antecedent),
+            total_receipts: Some(counts.total),
+            confidence: Some(confidence),
+            lift: Some(lift),
+            ..Default::default()
+        }
+    }
+
+    pub fn level(
+        source: ExplanationSource,
+        antecedent: &str,
+        target: &str,
+        counts: RuleCounts,
+        confidence: f64,
+        lift: f64,
+        share: f64,
+    ) -> Self {
+        Explanation {
+            source,
+            summary: format!(
+                "Receipts from {} also had something from {} in {} of {} cases ({} receipts in total); this product is {:.0}% of {} sales.",
+                antecedent,
+                target,
+                thousands(counts.together),
+                thousands(counts.antecedent),
+                thousands(counts.total),
+                share * 100.0,
+                target
+            ),
+            antecedent: Some(antecedent.to_string()),
+            receipts_together: Some(counts.together),
+            antecedent_receipts: Some(counts.antecedent),
+            total_receipts: Some(counts.total),
+            confidence: Some(confidence),
+            lift: Some(lift),
+            ..Default::default()
+        }
+    }
+
+    /// A rule of a similar product, borrowed by a product with too few sales of its own.
+    pub fn content_based(neighbour: i32, similarity: f64, borrowed: Option<&Explanation>) -> Self {
+        let mut explanation = borrowed.cloned().unwrap_or_default();
+        explanation.summary = format!(
+            "Borrowed from similar product {} (similarity {:.2}). {}",
+            neighbour, similarity, explanation.summary
+        )
+        .trim_end()
+        .to_string();
+        explanation.source = ExplanationSource::ContentBased;
+        explanation.similarity = Some(similarity);
+        explanation
+    }
+
+    pub fn collaborative() -> Self {
+        Explanation {
+            source: ExplanationSource::Collaborative,
+            summary: "Bought by customers whose purchase histories resemble this customer's.".to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+// The sale line a recommendation in impression `i` on receipt `r` has to come
+// after to count: the order's newest line when it was shown, or failing that
+// the first line of the product it was shown for.
+pub(crate) const AFTER_SHOWN: &str = "COALESCE(i.last_sale_id, (
+    SELECT MIN(o.sale_id) FROM sales o
+    WHERE o.receipt_id = r.receipt_id AND o.product_id = i.source_product_id), 0)";
+
+// How many impressions' worth of the average acceptance rate each rule starts
+// with, so a rule shown twice and taken once is not treated as a 50% winner.
+const PRIOR_IMPRESSIONS: f64 = 20.0;
+
+#[derive(Debug, Deserialize)]
+pub struct ShownRecommendation {
+    pub product_id: i32,
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpressionRequest {
+    pub receipt_no: i32,
+    pub source_product_id: Option<i32>,
+    pub staff: Option<String>,
+    pub model_version_id: Option<i32>,
+    pub variant_id: Option<i32>,
+    /// The newest sale line on the order when the recommendations were shown.
+    /// Defaults to the newest line already synced for the receipt.
+    pub last_sale_id: Option<i32>,
+    pub recommendations: Vec<ShownRecommendation>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionGrouping {
+    Rule,
+    Product,
+    Staff,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConversionQuery {
+    pub group_by: Option<ConversionGrouping>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ConversionStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_product_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recommended_product_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staff: Option<String>,
+    pub impressions: i64,
+    pub conversions: i64,
+    #[sqlx(skip)]
+    pub acceptance_rate: f64,
+}
+
+/// Acceptance counts used to re-rank the rules at training time.
+#[derive(Debug, Default)]
+pub struct FeedbackStats {
+    rules: HashMap<(i32, i32), (i64, i64)>,
+    products: HashMap<i32, (i64, i64)>,
+    average_rate: f64,
+}
+
+impl FeedbackStats {
+    /// Multiplier for a rule's score: above 1 when it converts better than the
+    /// average recommendation, below 1 when worse, 1 when it has never been shown.
+    pub fn boost(&self, source_product_id: i32, recommended_product_id: i32) -> f64 {
+        if self.average_rate <= 0.0 {
+            return 1.0;
+        }
+        let counts = self
+            .rules
+            .get(&(source_product_id, recommended_product_id))
+            .or_else(|| self.products.get(&recommended_product_id));
+        match counts {
+            Some((impressions, conversions)) => {
+                let smoothed = (*conversions as f64 + PRIOR_IMPRESSIONS * self.average_rate)
+                    / (*impressions as f64 + PRIOR_IMPRESSIONS);
+                smoothed / self.average_rate
+            }
+            None => 1.0,
+        }
+    }
+}
+
+pub async fn record_impressions(
+    pool: &PgPool,
+    request: &ImpressionRequest,
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (position, shown) in request.recommendations.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO recommendation_impressions
+                (receipt_no, source_product_id, recommended_product_id, position, score, staff,
+                 model_version_id, variant_id, last_sale_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, COALESCE($9, (
+                 SELECT MAX(s.sale_id) FROM sales s
+                 JOIN receipts r ON r.receipt_id = s.receipt_id
+                 WHERE r.receipt_no = $1
+             )))",
+        )
+        .bind(request.receipt_no)
+        .bind(request.source_product_id)
+        .bind(shown.product_id)
+        .bind(position as i32 + 1)
+        .bind(shown.score)
+        .bind(&request.staff)
+        .bind(request.model_version_id)
+        .bind(request.variant_id)
+        .bind(request.last_sale_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(request.recommendations.len())
+}
+
+/// Impressions on receipts that have been synced, and how many of them ended
+/// up as a sale of the recommended product on the same receipt, added after
+/// the recommendation was shown.
+pub async fn get_conversion_stats(
+    pool: &PgPool,
+    grouping: ConversionGrouping,
+) -> Result<Vec<ConversionStats>, sqlx::Error> {
+    let (columns, group_by) = match grouping {
+        ConversionGrouping::Rule => (
+            "i.source_product_id, i.recommended_product_id, NULL::VARCHAR AS staff",
+            "i.source_product_id, i.recommended_product_id",
+        ),
+        ConversionGrouping::Product => (
+            "NULL::INTEGER AS source_product_id, i.recommended_product_id, NULL::VARCHAR AS staff",
+            "i.recommended_product_id",
+        ),
+        ConversionGrouping::Staff => (
+            "NULL::INTEGER AS source_product_id, NULL::INTEGER AS recommended_product_id, i.staff",
+            "i.staff",
+        ),
+    };
+
+    let query = format!(
+        r#"
+        SELECT {columns},
+               COUNT(*) AS impressions,
+               COUNT(*) FILTER (WHERE EXISTS (
+                   SELECT 1 FROM sales s
+                   WHERE s.receipt_id = r.receipt_id
+                     AND s.product_id = i.recommended_product_id
+                     AND s.sale_id > {AFTER_SHOWN}
+               )) AS conversions
+        FROM recommendation_impressions i
+        JOIN receipts r ON r.receipt_no = i.receipt_no
+        GROUP BY {group_by}
+        ORDER BY impressions DESC
+        "#
+    );
+
+    let mut stats = sqlx::query_as::<_, ConversionStats>(&query)
+        .fetch_all(pool)
+        .await?;
+    for row in &mut stats {
+        row.acceptance_rate = row.conversions as f64 / row.impressions.max(1) as f64;
+    }
+    Ok(stats)
+}
+
+pub async fn load_feedback_stats(pool: &PgPool) -> Result<FeedbackStats, sqlx::Error> {
+    let rules = get_conversion_stats(pool, ConversionGrouping::Rule).await?;
+    let products = get_conversion_stats(pool, ConversionGrouping::Product).await?;
+
+    let impressions: i64 = products.iter().map(|p| p.impressions).sum();
+    let conversions: i64 = products.iter().map(|p| p.conversions).sum();
+
+    Ok(FeedbackStats {
+        rules: rules
+            .into_iter()
+            .filter_map(|r| {
+                Some(((r.source_product_id?, r.recommended_product_id?), (r.impressions, r.conversions)))
+            })
+            .collect(),
+        products: products
+            .into_iter()
+            .filter_map(|p| Some((p.recommended_product_id?, (p.impressions, p.conversions))))
+            .collect(),
+        average_rate: conversions as f64 / impressions.max(1) as f64,
+    })
+}
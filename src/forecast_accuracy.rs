@@ -0,0 +1,146 @@
+use crate::calendar;
+use crate::forecasting;
+use crate::ml::backtest::{self, Accuracy};
+use crate::ml::forecasting::{Method, WEEK};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BacktestOptions {
+    /// Days forecast from each origin.
+    pub horizon: Option<usize>,
+    pub origins: Option<usize>,
+    /// Days between consecutive origins.
+    pub step: Option<usize>,
+    pub history_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccuracyQuery {
+    pub product_id: Option<i32>,
+    /// Only the method each product's forecasts use.
+    pub selected: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct MethodAccuracy {
+    pub product_id: i32,
+    pub method: String,
+    pub mape: Option<f64>,
+    pub smape: f64,
+    pub mase: Option<f64>,
+    pub bias: f64,
+    pub forecasts: i32,
+    pub origins: i32,
+    pub horizon_days: i32,
+    pub selected: bool,
+    pub evaluated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BacktestSummary {
+    pub products: usize,
+    /// How many products each method was selected for.
+    pub selected: HashMap<String, usize>,
+}
+
+/// Backtests every candidate method on every product and replaces the stored
+/// accuracy table, marking the best method per product as selected.
+pub async fn run_backtests(pool: &PgPool, options: &BacktestOptions) -> Result<BacktestSummary> {
+    let horizon = options.horizon.unwrap_or(7).max(1);
+    let origins = options.origins.unwrap_or(6).max(1);
+    let step = options.step.unwrap_or(7).max(1);
+    let series = forecasting::get_demand_series(pool, options.history_days, None).await?;
+    let calendar = calendar::load_calendar(pool).await?;
+
+    let results = forecasting::fit_blocking(move || {
+        let mut results: Vec<(i32, Vec<Accuracy>, Method)> = Vec::new();
+        for series in series.values() {
+            let features = calendar.features(series.start, series.daily.len());
+            let accuracies: Vec<Accuracy> = Method::CANDIDATES
+                .iter()
+                .filter_map(|method| {
+                    backtest::rolling_origin(&series.daily, &features, WEEK, *method, horizon, origins, step)
+                })
+                .collect();
+            if let Some(best) = backtest::best(&accuracies) {
+                let method = best.method;
+                results.push((series.product_id, accuracies, method));
+            }
+        }
+        results
+    })
+    .await?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM forecast_accuracy").execute(&mut *tx).await?;
+    for (product_id, accuracies, selected) in &results {
+        for accuracy in accuracies {
+            sqlx::query(
+                r#"
+                INSERT INTO forecast_accuracy
+                    (product_id, method, mape, smape, mase, bias, forecasts, origins, horizon_days, selected)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                "#,
+            )
+            .bind(product_id)
+            .bind(accuracy.method.as_str())
+            .bind(accuracy.mape)
+            .bind(accuracy.smape)
+            .bind(accuracy.mase)
+            .bind(accuracy.bias)
+            .bind(accuracy.forecasts as i32)
+            .bind(accuracy.origins as i32)
+            .bind(horizon as i32)
+            .bind(accuracy.method == *selected)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+
+    let mut selected: HashMap<String, usize> = HashMap::new();
+    for (_, _, method) in &results {
+        *selected.entry(method.as_str().to_string()).or_insert(0) += 1;
+    }
+    Ok(BacktestSummary {
+        products: results.len(),
+        selected,
+    })
+}
+
+pub async fn get_forecast_accuracy(
+    pool: &PgPool,
+    query: &AccuracyQuery,
+) -> Result<Vec<MethodAccuracy>, sqlx::Error> {
+    sqlx::query_as::<_, MethodAccuracy>(
+        r#"
+        SELECT product_id, method, mape, smape, mase, bias, forecasts, origins, horizon_days,
+               selected, evaluated_at
+        FROM forecast_accuracy
+        WHERE ($1::INTEGER IS NULL OR product_id = $1)
+          AND (NOT $2 OR selected)
+        ORDER BY product_id, mase NULLS LAST, smape
+        "#,
+    )
+    .bind(query.product_id)
+    .bind(query.selected.unwrap_or(false))
+    .fetch_all(pool)
+    .await
+}
+
+/// The method the last backtest picked for each product.
+pub async fn get_selected_methods(pool: &PgPool) -> Result<HashMap<i32, Method>, sqlx::Error> {
+    let query = AccuracyQuery {
+        product_id: None,
+        selected: Some(true),
+    };
+    Ok(get_forecast_accuracy(pool, &query)
+        .await?
+        .into_iter()
+        .filter_map(|row| Some((row.product_id, Method::parse(&row.method)?)))
+        .collect())
+}
@@ -0,0 +1,435 @@
+use crate::calendar::{self, Calendar};
+use crate::forecast_accuracy;
+use crate::ml::forecasting::{Method, WEEK};
+use crate::ml::regressors::{self, Uplift};
+use anyhow::Result;
+use chrono::{Datelike, Days, Duration, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Longest horizon forecasts are made for.
+pub const MAX_HORIZON: usize = 90;
+// Longest history fitted; anything asked for beyond it is treated as all of it.
+const MAX_HISTORY_DAYS: i64 = 10 * 366;
+/// Days ahead the background job stores for every product.
+pub const STORED_HORIZON_DAYS: usize = 28;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ForecastOptions {
+    /// Forces a method; by default the one the last backtest selected for the
+    /// product is used, or the best one its history allows.
+    pub method: Option<Method>,
+    pub horizon: Option<usize>,
+    /// Days of history fitted, counted back from the latest sale.
+    pub history_days: Option<i64>,
+    pub department: Option<String>,
+    pub category: Option<String>,
+    /// Set false to leave out holiday, payday and event effects.
+    pub calendar: Option<bool>,
+}
+
+/// Units sold per day of one product, with days without sales filled in as
+/// zeros, plus how its units spread over the hours of the day.
+#[derive(Debug, Clone)]
+pub struct DemandSeries {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub department: Option<String>,
+    pub category: Option<String>,
+    pub start: NaiveDate,
+    pub daily: Vec<f64>,
+    /// Units sold in each hour of the day over the whole history.
+    pub hourly: [f64; 24],
+}
+
+impl DemandSeries {
+    pub fn end(&self) -> NaiveDate {
+        self.start + Duration::days(self.daily.len() as i64 - 1)
+    }
+
+    /// Average units per day of week, Monday first.
+    pub fn weekday_profile(&self) -> [f64; WEEK] {
+        let mut totals = [0.0; WEEK];
+        let mut days = [0usize; WEEK];
+        for (offset, units) in self.daily.iter().enumerate() {
+            let weekday = (self.start + Duration::days(offset as i64))
+                .weekday()
+                .num_days_from_monday() as usize;
+            totals[weekday] += units;
+            days[weekday] += 1;
+        }
+        let mut profile = [0.0; WEEK];
+        for weekday in 0..WEEK {
+            if days[weekday] > 0 {
+                profile[weekday] = totals[weekday] / days[weekday] as f64;
+            }
+        }
+        profile
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyForecast {
+    pub date: NaiveDate,
+    pub units: f64,
+    pub lower_80: f64,
+    pub upper_80: f64,
+    pub lower_95: f64,
+    pub upper_95: f64,
+    /// Holidays, paydays and events falling on the day.
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekdayDemand {
+    pub weekday: String,
+    pub average_units: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DemandForecast {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub method: Method,
+    pub history_start: NaiveDate,
+    pub history_end: NaiveDate,
+    /// Forecast units for the first day after the history.
+    pub units_per_day: f64,
+    pub residual_sd: f64,
+    /// Calendar effects estimated from the history and applied to the forecast.
+    pub calendar_uplifts: Vec<Uplift>,
+    pub daily: Vec<DailyForecast>,
+    pub weekday_profile: Vec<WeekdayDemand>,
+    /// Share of a day's units sold in each hour, 0-23.
+    pub hourly_profile: Vec<f64>,
+}
+
+/// One run of the background forecast job; its id is the model version
+/// stored with each forecast row.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ForecastRun {
+    pub id: i32,
+    pub generated_at: NaiveDateTime,
+    pub history_end: Option<NaiveDate>,
+    pub products: i32,
+    pub horizon_days: i32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct LatestRun {
+    #[sqlx(flatten)]
+    run: ForecastRun,
+    age_minutes: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SaleRow {
+    product_id: i32,
+    name: Option<String>,
+    department: Option<String>,
+    category: Option<String>,
+    transaction_date: NaiveDateTime,
+    quantity: f32,
+}
+
+struct ProductSales {
+    name: Option<String>,
+    department: Option<String>,
+    category: Option<String>,
+    days: HashMap<NaiveDate, f64>,
+    hourly: [f64; 24],
+}
+
+/// Runs CPU-bound model fitting on the blocking thread pool, so fitting
+/// hundreds of series does not hold up the async workers serving requests.
+pub async fn fit_blocking<T, F>(fit: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    Ok(tokio::task::spawn_blocking(fit).await?)
+}
+
+pub async fn get_demand_forecasts(pool: &PgPool, options: &ForecastOptions) -> Result<Vec<DemandForecast>> {
+    let series = get_demand_series(pool, options.history_days, None).await?;
+    let selected = forecast_accuracy::get_selected_methods(pool).await?;
+    let calendar = calendar::load_calendar(pool).await?;
+    let matches = |filter: &Option<String>, value: &Option<String>| {
+        filter
+            .as_deref()
+            .is_none_or(|f| value.as_deref().is_some_and(|v| v.eq_ignore_ascii_case(f)))
+    };
+    let series: Vec<DemandSeries> = series
+        .into_values()
+        .filter(|s| matches(&options.department, &s.department))
+        .filter(|s| matches(&options.category, &s.category))
+        .collect();
+    let options = options.clone();
+    let mut forecasts: Vec<DemandForecast> = fit_blocking(move || {
+        series
+            .iter()
+            .map(|series| forecast_series(series, &options, &selected, &calendar))
+            .collect()
+    })
+    .await?;
+    forecasts.sort_by_key(|f| f.product_id);
+    Ok(forecasts)
+}
+
+/// `None` when the product has no sales in the history window.
+pub async fn get_product_forecast(
+    pool: &PgPool,
+    product_id: i32,
+    options: &ForecastOptions,
+) -> Result<Option<DemandForecast>> {
+    let mut series = get_demand_series(pool, options.history_days, Some(product_id)).await?;
+    let Some(series) = series.remove(&product_id) else {
+        return Ok(None);
+    };
+    let selected = forecast_accuracy::get_selected_methods(pool).await?;
+    let calendar = calendar::load_calendar(pool).await?;
+    let options = options.clone();
+    let forecast = fit_blocking(move || forecast_series(&series, &options, &selected, &calendar)).await?;
+    Ok(Some(forecast))
+}
+
+pub fn forecast_series(
+    series: &DemandSeries,
+    options: &ForecastOptions,
+    selected: &HashMap<i32, Method>,
+    calendar: &Calendar,
+) -> DemandForecast {
+    let horizon = options.horizon.unwrap_or(7).clamp(1, MAX_HORIZON);
+    let method = options.method.or_else(|| selected.get(&series.product_id).copied());
+    let end = series.end();
+    let features = if options.calendar.unwrap_or(true) {
+        calendar.features(series.start, series.daily.len() + horizon)
+    } else {
+        Vec::new()
+    };
+    let (forecast, calendar_uplifts) =
+        regressors::forecast(&series.daily, &features, WEEK, horizon, method);
+    let mut events: HashMap<NaiveDate, Vec<String>> = HashMap::new();
+    for day in calendar.days(end + Duration::days(1), end + Duration::days(horizon as i64)) {
+        events.entry(day.date).or_default().push(day.name);
+    }
+    let interval_80 = forecast.interval(0.8);
+    let interval_95 = forecast.interval(0.95);
+
+    let hourly_total: f64 = series.hourly.iter().sum();
+    let hourly_profile = series
+        .hourly
+        .iter()
+        .map(|units| if hourly_total > 0.0 { units / hourly_total } else { 0.0 })
+        .collect();
+
+    DemandForecast {
+        product_id: series.product_id,
+        name: series.name.clone(),
+        method: forecast.method,
+        history_start: series.start,
+        history_end: end,
+        units_per_day: forecast.points[0],
+        residual_sd: forecast.residual_sd,
+        calendar_uplifts,
+        daily: forecast
+            .points
+            .iter()
+            .enumerate()
+            .map(|(h, units)| {
+                let date = end + Duration::days(h as i64 + 1);
+                DailyForecast {
+                    date,
+                    units: *units,
+                    lower_80: interval_80[h].0,
+                    upper_80: interval_80[h].1,
+                    lower_95: interval_95[h].0,
+                    upper_95: interval_95[h].1,
+                    events: events.remove(&date).unwrap_or_default(),
+                }
+            })
+            .collect(),
+        weekday_profile: series
+            .weekday_profile()
+            .iter()
+            .enumerate()
+            .map(|(weekday, units)| WeekdayDemand {
+                weekday: weekday_name(weekday).to_string(),
+                average_units: *units,
+            })
+            .collect(),
+        hourly_profile,
+    }
+}
+
+/// Daily series for every product that has sold, or just `product_id`. All
+/// series end on the day of the latest sale in the data rather than today,
+/// since the sync can lag; each starts at the product's first sale within the
+/// history window.
+pub async fn get_demand_series(
+    pool: &PgPool,
+    history_days: Option<i64>,
+    product_id: Option<i32>,
+) -> Result<HashMap<i32, DemandSeries>, sqlx::Error> {
+    let latest = sqlx::query_scalar::<_, Option<NaiveDateTime>>(
+        "SELECT MAX(transaction_date) FROM receipts",
+    )
+    .fetch_one(pool)
+    .await?;
+    let Some(end) = latest.map(|latest| latest.date()) else {
+        return Ok(HashMap::new());
+    };
+
+    let rows = sqlx::query_as::<_, SaleRow>(
+        r#"
+        SELECT s.product_id, p.name, p.department, p.category, r.transaction_date, s.quantity
+        FROM sales s
+        JOIN receipts r ON s.receipt_id = r.receipt_id
+        JOIN products p ON s.product_id = p.product_id
+        WHERE r.transaction_date IS NOT NULL AND s.quantity IS NOT NULL
+          AND ($1::INTEGER IS NULL OR s.product_id = $1)
+        "#,
+    )
+    .bind(product_id)
+    .fetch_all(pool)
+    .await?;
+
+    let window_start = history_days.map(|days| {
+        let days = days.clamp(1, MAX_HISTORY_DAYS) as u64;
+        end.checked_sub_days(Days::new(days - 1)).unwrap_or(NaiveDate::MIN)
+    });
+
+    let mut sales: HashMap<i32, ProductSales> = HashMap::new();
+    for row in rows {
+        let date = row.transaction_date.date();
+        if window_start.is_some_and(|start| date < start) {
+            continue;
+        }
+        let product = sales.entry(row.product_id).or_insert_with(|| ProductSales {
+            name: row.name.clone(),
+            department: row.department.clone(),
+            category: row.category.clone(),
+            days: HashMap::new(),
+            hourly: [0.0; 24],
+        });
+        *product.days.entry(date).or_insert(0.0) += row.quantity as f64;
+        product.hourly[row.transaction_date.hour() as usize] += row.quantity as f64;
+    }
+
+    Ok(sales
+        .into_iter()
+        .filter_map(|(product_id, sales)| {
+            let ProductSales {
+                name,
+                department,
+                category,
+                days,
+                hourly,
+            } = sales;
+            let start = *days.keys().min()?;
+            let daily = (0..=(end - start).num_days())
+                .map(|offset| days.get(&(start + Duration::days(offset))).copied().unwrap_or(0.0))
+                .collect();
+            Some((
+                product_id,
+                DemandSeries {
+                    product_id,
+                    name,
+                    department,
+                    category,
+                    start,
+                    daily,
+                    hourly,
+                },
+            ))
+        })
+        .collect())
+}
+
+fn weekday_name(weekday: usize) -> &'static str {
+    ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"][weekday]
+}
+
+/// Forecasts every product with its selected method and replaces the stored
+/// forecasts with them in one transaction, so readers never see a half-written run.
+pub async fn store_forecasts(pool: &PgPool) -> Result<ForecastRun> {
+    let options = ForecastOptions {
+        horizon: Some(STORED_HORIZON_DAYS),
+        ..Default::default()
+    };
+    let forecasts = get_demand_forecasts(pool, &options).await?;
+    let history_end = forecasts.iter().map(|f| f.history_end).max();
+
+    let mut columns: (Vec<i32>, Vec<NaiveDate>, Vec<String>, Vec<f64>) = Default::default();
+    let mut intervals: (Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>) = Default::default();
+    for forecast in &forecasts {
+        for day in &forecast.daily {
+            columns.0.push(forecast.product_id);
+            columns.1.push(day.date);
+            columns.2.push(forecast.method.as_str().to_string());
+            columns.3.push(day.units);
+            intervals.0.push(day.lower_80);
+            intervals.1.push(day.upper_80);
+            intervals.2.push(day.lower_95);
+            intervals.3.push(day.upper_95);
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+    let run = sqlx::query_as::<_, ForecastRun>(
+        "INSERT INTO forecast_runs (history_end, products, horizon_days)
+         VALUES ($1, $2, $3)
+         RETURNING id, generated_at, history_end, products, horizon_days",
+    )
+    .bind(history_end)
+    .bind(forecasts.len() as i32)
+    .bind(STORED_HORIZON_DAYS as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+    // Older runs take their forecasts with them.
+    sqlx::query("DELETE FROM forecast_runs WHERE id <> $1")
+        .bind(run.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO demand_forecasts
+            (product_id, forecast_date, model_version, method, units,
+             lower_80, upper_80, lower_95, upper_95)
+        SELECT product_id, forecast_date, $1, method, units, lower_80, upper_80, lower_95, upper_95
+        FROM UNNEST($2::INTEGER[], $3::DATE[], $4::VARCHAR[], $5::DOUBLE PRECISION[],
+                    $6::DOUBLE PRECISION[], $7::DOUBLE PRECISION[], $8::DOUBLE PRECISION[],
+                    $9::DOUBLE PRECISION[])
+            AS t(product_id, forecast_date, method, units, lower_80, upper_80, lower_95, upper_95)
+        "#,
+    )
+    .bind(run.id)
+    .bind(&columns.0)
+    .bind(&columns.1)
+    .bind(&columns.2)
+    .bind(&columns.3)
+    .bind(&intervals.0)
+    .bind(&intervals.1)
+    .bind(&intervals.2)
+    .bind(&intervals.3)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(run)
+}
+
+/// The run the stored forecasts belong to and its age in minutes, taken in
+/// the database whose clock stamped it; `None` before the first run.
+pub async fn get_latest_run(pool: &PgPool) -> Result<Option<(ForecastRun, i64)>, sqlx::Error> {
+    let latest = sqlx::query_as::<_, LatestRun>(
+        "SELECT id, generated_at, history_end, products, horizon_days,
+                (EXTRACT(EPOCH FROM (LOCALTIMESTAMP - generated_at)) / 60)::BIGINT AS age_minutes
+         FROM forecast_runs
+         ORDER BY id DESC
+         LIMIT 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(latest.map(|latest| (latest.run, latest.age_minutes)))
+}
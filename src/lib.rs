@@ -0,0 +1,24 @@
+pub mod agent;
+pub mod bundles;
+pub mod calendar;
+pub mod dayparts;
+pub mod errors;
+pub mod evaluation;
+pub mod experiments;
+pub mod explanations;
+pub mod feedback;
+pub mod forecast_accuracy;
+pub mod forecasting;
+pub mod market_intelligence;
+pub mod ml;
+pub mod next_visit;
+pub mod recipes;
+pub mod recommendations;
+pub mod replenishment;
+pub mod stock_alerts;
+pub mod stock_movements;
+pub mod stock_optimization;
+pub mod substitutes;
+pub mod sync;
+pub mod trend_discovery;
+pub mod waste;
@@ -1,26 +1,66 @@
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{delete, get, post, web, App, HttpResponse, HttpResponseBuilder, HttpServer, Responder};
 use anyhow::Context;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{MySqlPool, PgPool};
-use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock};
 use tracing::{error, info, warn};
 
-mod ml;
-mod recommendations;
-mod stock_optimization;
-mod trend_discovery;
-mod market_intelligence;
-mod sync;
-mod agent;
+use ai_backend::{
+    agent,
+    bundles,
+    calendar,
+    errors::RequestError,
+    evaluation,
+    experiments,
+    feedback,
+    forecast_accuracy,
+    forecasting,
+    market_intelligence,
+    next_visit,
+    recipes,
+    recommendations,
+    replenishment,
+    stock_alerts,
+    stock_movements,
+    stock_optimization,
+    substitutes,
+    sync,
+    trend_discovery,
+    waste,
+};
 
 
 // Define a struct to hold our application state
 struct AppState {
     pool: PgPool,
-    recommendation_cache: RwLock<HashMap<i32, Vec<recommendations::Recommendation>>>,
+    recommendation_cache: RwLock<recommendations::ModelStore>,
+    recommendation_config: recommendations::RecommendationConfig,
+    replenishment_config: replenishment::ReplenishmentConfig,
+    /// Wakes the background retrainer, for changes that need fresh models but
+    /// should not hold up the request that made them.
+    retrain_requested: Arc<Notify>,
+}
+
+// Request errors go back to the caller as 400 or 404 with their message;
+// anything else is logged and answered with a bare 500.
+fn error_response(context: &str, e: anyhow::Error) -> HttpResponse {
+    match e.downcast_ref::<RequestError>() {
+        Some(RequestError::Invalid(message)) => HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": message
+        })),
+        Some(RequestError::NotFound(message)) => HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": message
+        })),
+        None => {
+            error!("{}: {:?}", context, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -35,28 +75,335 @@ async fn health() -> impl Responder {
     })
 }
 
+#[derive(Deserialize)]
+struct RecommendationQuery {
+    daypart: Option<String>,
+    terminal_id: Option<String>,
+    explain: Option<bool>,
+    score_by: Option<recommendations::Scoring>,
+}
+
+#[derive(Deserialize)]
+struct CustomerRecommendationQuery {
+    explain: Option<bool>,
+    score_by: Option<recommendations::Scoring>,
+}
+
+// Tells the POS which model answered, so it can send it back with impressions.
+fn served_by(
+    builder: &mut HttpResponseBuilder,
+    version: Option<i32>,
+    assignment: Option<&experiments::Assignment>,
+) {
+    if let Some(version) = version {
+        builder.insert_header(("X-Model-Version", version.to_string()));
+    }
+    if let Some(assignment) = assignment {
+        builder.insert_header(("X-Experiment-Id", assignment.experiment_id.to_string()));
+        builder.insert_header(("X-Experiment-Variant", assignment.variant_id.to_string()));
+    }
+}
+
+async fn log_exposure(
+    pool: &PgPool,
+    assignment: Option<&experiments::Assignment>,
+    product_id: Option<i32>,
+    customer_id: Option<i32>,
+) {
+    if let Some(assignment) = assignment {
+        if let Err(e) = experiments::log_exposure(pool, assignment, product_id, customer_id).await {
+            warn!("Failed to log experiment exposure: {:?}", e);
+        }
+    }
+}
+
 #[get("/api/recommendations/{product_id}")]
 async fn get_recommendations(
     state: web::Data<AppState>,
     product_id: web::Path<i32>,
+    query: web::Query<RecommendationQuery>,
 ) -> impl Responder {
     let product_id = product_id.into_inner();
-    let cache = state.recommendation_cache.read().await;
-    let recommendations = recommendations::get_recommendations_from_cache(
-        &cache,
-        product_id,
+    let daypart = state
+        .recommendation_config
+        .dayparts
+        .resolve(query.daypart.as_deref(), chrono::Local::now().naive_local());
+    let (cached, version, assignment) = {
+        let cache = state.recommendation_cache.read().await;
+        let assigned = query.terminal_id.as_deref().and_then(|terminal_id| {
+            experiments::assign(&cache.experiments, experiments::ExperimentUnit::Terminal, terminal_id)
+        });
+        let model = cache.model_for(assigned.as_ref().map(|(_, variant)| *variant));
+        let mut cached =
+            recommendations::get_recommendations_from_cache(model, product_id, daypart.as_deref());
+        let scoring = query.score_by.unwrap_or(state.recommendation_config.scoring);
+        recommendations::apply_scoring(model, &mut cached, scoring);
+        recommendations::explain(model, &mut cached, query.explain.unwrap_or(false));
+        (
+            cached,
+            model.version,
+            assigned.map(|(assignment, _)| assignment),
+        )
+    };
+    log_exposure(&state.pool, assignment.as_ref(), Some(product_id), None).await;
+    match recommendations::enrich_recommendations(&state.pool, cached).await {
+        Ok(recommendations) => {
+            let mut response = HttpResponse::Ok();
+            served_by(&mut response, version, assignment.as_ref());
+            response.json(recommendations)
+        }
+        Err(e) => {
+            error!("Recommendation lookup error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/customers/{customer_id}/recommendations")]
+async fn get_customer_recommendations(
+    state: web::Data<AppState>,
+    customer_id: web::Path<i32>,
+    query: web::Query<CustomerRecommendationQuery>,
+) -> impl Responder {
+    let customer_id = customer_id.into_inner();
+    let history = match recommendations::get_customer_history(&state.pool, customer_id).await {
+        Ok(history) => history,
+        Err(e) => {
+            error!("Customer history error: {:?}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let (cached, version, assignment) = {
+        let cache = state.recommendation_cache.read().await;
+        let assigned = experiments::assign(
+            &cache.experiments,
+            experiments::ExperimentUnit::Customer,
+            &customer_id.to_string(),
+        );
+        let variant = assigned.as_ref().map(|(_, variant)| *variant);
+        let model = cache.model_for(variant);
+        let personalized = variant.is_none_or(|v| v.is_personalized());
+        let mut cached =
+            recommendations::get_customer_recommendations_from_cache(model, &history, personalized);
+        let scoring = query.score_by.unwrap_or(state.recommendation_config.scoring);
+        recommendations::apply_scoring(model, &mut cached, scoring);
+        recommendations::explain(model, &mut cached, query.explain.unwrap_or(false));
+        (
+            cached,
+            model.version,
+            assigned.map(|(assignment, _)| assignment),
+        )
+    };
+    log_exposure(&state.pool, assignment.as_ref(), None, Some(customer_id)).await;
+    match recommendations::enrich_recommendations(&state.pool, cached).await {
+        Ok(recommendations) => {
+            let mut response = HttpResponse::Ok();
+            served_by(&mut response, version, assignment.as_ref());
+            response.json(recommendations)
+        }
+        Err(e) => {
+            error!("Recommendation lookup error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/customers/{customer_id}/next_visit")]
+async fn get_next_visit_suggestions(
+    state: web::Data<AppState>,
+    customer_id: web::Path<i32>,
+    query: web::Query<next_visit::SequenceOptions>,
+) -> impl Responder {
+    let patterns = state.recommendation_cache.read().await.visit_patterns.clone();
+    match next_visit::get_next_visit_suggestions(&state.pool, &patterns, customer_id.into_inner(), &query)
+        .await
+    {
+        Ok(suggestions) => HttpResponse::Ok().json(suggestions),
+        Err(e) => {
+            error!("Next-visit suggestions error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/sequential_patterns")]
+async fn get_sequential_patterns(
+    state: web::Data<AppState>,
+    query: web::Query<next_visit::SequenceOptions>,
+) -> impl Responder {
+    let patterns = state.recommendation_cache.read().await.visit_patterns.clone();
+    match next_visit::get_sequential_patterns(&state.pool, &patterns, &query).await {
+        Ok(patterns) => HttpResponse::Ok().json(patterns),
+        Err(e) => {
+            error!("Sequential patterns error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/recommendation_impressions")]
+async fn record_recommendation_impressions(
+    state: web::Data<AppState>,
+    request: web::Json<feedback::ImpressionRequest>,
+) -> impl Responder {
+    match feedback::record_impressions(&state.pool, &request).await {
+        Ok(recorded) => HttpResponse::Ok().json(serde_json::json!({
+            "status": "success",
+            "recorded": recorded
+        })),
+        Err(e) => {
+            error!("Recording impressions failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": e.to_string()
+            }))
+        }
+    }
+}
+
+#[get("/api/recommendation_conversions")]
+async fn get_recommendation_conversions(
+    state: web::Data<AppState>,
+    query: web::Query<feedback::ConversionQuery>,
+) -> impl Responder {
+    let grouping = query.group_by.unwrap_or(feedback::ConversionGrouping::Rule);
+    match feedback::get_conversion_stats(&state.pool, grouping).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            error!("Recommendation conversions error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Retrains every model and swaps the cache; shared by the endpoints that need fresh models.
+async fn retrain(state: &AppState) -> anyhow::Result<usize> {
+    let new_cache =
+        recommendations::train_and_cache_recommendations(&state.pool, &state.recommendation_config)
+            .await?;
+    let count = new_cache.len();
+    spawn_model_evaluation(
+        state.pool.clone(),
+        state.recommendation_config.clone(),
+        new_cache.primary.version,
     );
-    HttpResponse::Ok().json(recommendations)
+    let mut cache = state.recommendation_cache.write().await;
+    *cache = new_cache;
+    Ok(count)
+}
+
+// Folds newly synced sales into the live model, or retrains from scratch once
+// the last full training is old enough. The swap is skipped if another
+// retrain replaced the model in the meantime.
+async fn refresh(state: &AppState) -> anyhow::Result<()> {
+    let config = &state.recommendation_config;
+    // Work on a copy so requests and retraining are not held up while the
+    // refresh reads new sales and rebuilds the rules.
+    let current = {
+        let cache = state.recommendation_cache.read().await;
+        let due = cache
+            .fully_trained_at
+            .is_none_or(|at| at.elapsed() >= config.full_retrain_after);
+        (!due).then(|| cache.clone())
+    };
+    let refreshed = match current {
+        None => None,
+        Some(current) => Some((
+            current.primary.version,
+            recommendations::refresh_recommendations(&state.pool, config, current).await?,
+        )),
+    };
+
+    match refreshed {
+        None => {
+            info!("Last full training is stale; retraining recommendation model...");
+            retrain(state).await?;
+        }
+        Some((_, None)) => info!("No new sales since the last refresh."),
+        Some((trained_version, Some(store))) => {
+            let mut cache = state.recommendation_cache.write().await;
+            if cache.primary.version == trained_version {
+                spawn_model_evaluation(state.pool.clone(), config.clone(), store.primary.version);
+                *cache = store;
+            } else {
+                info!("Model was retrained during refresh; discarding refreshed model.");
+            }
+        }
+    }
+    Ok(())
+}
+
+// Waits for syncs that brought in sales, then refreshes once the syncs have
+// been quiet for the debounce period.
+fn spawn_model_refresher(state: web::Data<AppState>, sales_synced: Arc<Notify>) {
+    tokio::spawn(async move {
+        let debounce = state.recommendation_config.refresh_debounce;
+        loop {
+            sales_synced.notified().await;
+            while tokio::time::timeout(debounce, sales_synced.notified())
+                .await
+                .is_ok()
+            {}
+            if let Err(e) = refresh(&state).await {
+                error!("Refreshing recommendation model failed: {:?}", e);
+            }
+        }
+    });
+}
+
+// Retrains whenever a change to the experiments asks for it; requests made
+// while a retrain runs are served by the next one.
+fn spawn_model_retrainer(state: web::Data<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.retrain_requested.notified().await;
+            match retrain(&state).await {
+                Ok(count) => info!("Background retraining complete with {} items cached.", count),
+                Err(e) => error!("Background retraining failed: {:?}", e),
+            }
+        }
+    });
+}
+
+// Re-selects each product's forecasting method by backtest, then stores fresh
+// forecasts for the stock endpoint to serve and raises stock alerts from them.
+// Runs once at startup and again whenever notified.
+fn spawn_forecast_refresher(
+    pool: PgPool,
+    replenishment_config: replenishment::ReplenishmentConfig,
+    forecasts_stale: Arc<Notify>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let options = forecast_accuracy::BacktestOptions::default();
+            match forecast_accuracy::run_backtests(&pool, &options).await {
+                Ok(summary) => info!("Forecast backtest selected methods for {} products.", summary.products),
+                Err(e) => warn!("Forecast backtest failed: {:?}", e),
+            }
+            match forecasting::store_forecasts(&pool).await {
+                Ok(run) => info!(
+                    "Stored demand forecasts version {} for {} products.",
+                    run.id, run.products
+                ),
+                Err(e) => error!("Storing demand forecasts failed: {:?}", e),
+            }
+            match stock_alerts::raise_stock_alerts(&pool, &replenishment_config).await {
+                Ok(summary) => info!(
+                    "Stock alerts: {} products at risk, {} resolved, {} past stock-outs found.",
+                    summary.at_risk, summary.resolved, summary.historical_stock_outs
+                ),
+                Err(e) => error!("Raising stock alerts failed: {:?}", e),
+            }
+            forecasts_stale.notified().await;
+        }
+    });
 }
 
 #[post("/api/retrain")]
 async fn retrain_model(state: web::Data<AppState>) -> impl Responder {
     info!("Manual retraining triggered via API...");
-    match recommendations::train_and_cache_recommendations(&state.pool).await {
-        Ok(new_cache) => {
-            let count = new_cache.len();
-            let mut cache = state.recommendation_cache.write().await;
-            *cache = new_cache;
+    match retrain(&state).await {
+        Ok(count) => {
             info!("Retraining complete. Cache updated with {} items.", count);
             HttpResponse::Ok().json(serde_json::json!({
                 "status": "success",
@@ -73,6 +420,121 @@ async fn retrain_model(state: web::Data<AppState>) -> impl Responder {
     }
 }
 
+#[get("/api/experiments")]
+async fn get_experiments(state: web::Data<AppState>) -> impl Responder {
+    match experiments::get_experiments(&state.pool, false).await {
+        Ok(experiments) => HttpResponse::Ok().json(experiments),
+        Err(e) => {
+            error!("Experiments error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/experiments")]
+async fn create_experiment(
+    state: web::Data<AppState>,
+    new_experiment: web::Json<experiments::NewExperiment>,
+) -> impl Responder {
+    match experiments::create_experiment(&state.pool, &new_experiment).await {
+        Ok(experiment) => {
+            // Variants with their own thresholds need their models trained before they can serve.
+            state.retrain_requested.notify_one();
+            HttpResponse::Ok().json(experiment)
+        }
+        Err(e) => error_response("Creating experiment failed", e),
+    }
+}
+
+#[post("/api/experiments/{experiment_id}/stop")]
+async fn stop_experiment(
+    state: web::Data<AppState>,
+    experiment_id: web::Path<i32>,
+) -> impl Responder {
+    match experiments::stop_experiment(&state.pool, experiment_id.into_inner()).await {
+        Ok(true) => {
+            state.retrain_requested.notify_one();
+            HttpResponse::Ok().json(serde_json::json!({ "status": "success" }))
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Stopping experiment failed: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/experiments/{experiment_id}/results")]
+async fn get_experiment_results(
+    state: web::Data<AppState>,
+    experiment_id: web::Path<i32>,
+) -> impl Responder {
+    match experiments::get_experiment_results(&state.pool, experiment_id.into_inner()).await {
+        Ok(Some(results)) => HttpResponse::Ok().json(results),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Experiment results error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/recommendation_evaluations")]
+async fn get_recommendation_evaluations(state: web::Data<AppState>) -> impl Responder {
+    match evaluation::get_evaluations(&state.pool).await {
+        Ok(evaluations) => HttpResponse::Ok().json(evaluations),
+        Err(e) => {
+            error!("Recommendation evaluations error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Scores a freshly trained model on a time-based holdout without blocking the caller.
+fn spawn_model_evaluation(
+    pool: PgPool,
+    config: recommendations::RecommendationConfig,
+    version: Option<i32>,
+) {
+    tokio::spawn(async move {
+        match evaluation::evaluate_model_version(&pool, &config, version).await {
+            Ok(report) => info!(
+                "Model version {:?} evaluated: hit-rate@{} {:.3}, MRR {:.3} over {} cases.",
+                version,
+                report.metrics.k,
+                report.metrics.hit_rate,
+                report.metrics.mrr,
+                report.metrics.cases
+            ),
+            Err(e) => warn!("Model version {:?} was not evaluated: {:?}", version, e),
+        }
+    });
+}
+
+#[get("/api/bundles")]
+async fn get_bundles(
+    state: web::Data<AppState>,
+    query: web::Query<bundles::BundleOptions>,
+) -> impl Responder {
+    match bundles::get_bundle_suggestions(&state.pool, &query).await {
+        Ok(bundles) => HttpResponse::Ok().json(bundles),
+        Err(e) => error_response("Bundle suggestions error", e),
+    }
+}
+
+#[get("/api/products/{product_id}/substitutes")]
+async fn get_substitutes(
+    state: web::Data<AppState>,
+    product_id: web::Path<i32>,
+    query: web::Query<substitutes::SubstituteOptions>,
+) -> impl Responder {
+    match substitutes::get_substitutes(&state.pool, product_id.into_inner(), &query).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => error_response("Substitutes error", e),
+    }
+}
+
 #[get("/api/stock_optimization")]
 async fn get_stock_optimization(state: web::Data<AppState>) -> impl Responder {
     match stock_optimization::get_stock_optimization(&state.pool).await {
@@ -84,6 +546,311 @@ async fn get_stock_optimization(state: web::Data<AppState>) -> impl Responder {
     }
 }
 
+#[get("/api/recipes")]
+async fn get_recipes(state: web::Data<AppState>) -> impl Responder {
+    match recipes::get_recipes(&state.pool).await {
+        Ok(recipes) => HttpResponse::Ok().json(recipes),
+        Err(e) => {
+            error!("Recipes error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/recipes")]
+async fn save_recipe(
+    state: web::Data<AppState>,
+    new_recipe: web::Json<recipes::NewRecipe>,
+) -> impl Responder {
+    match recipes::save_recipe(&state.pool, &new_recipe).await {
+        Ok(recipe) => HttpResponse::Ok().json(recipe),
+        Err(e) => error_response("Saving recipe failed", e),
+    }
+}
+
+#[get("/api/recipes/{dish_product_id}")]
+async fn get_recipe(state: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
+    let dish_product_id = path.into_inner();
+    match recipes::get_recipe(&state.pool, dish_product_id).await {
+        Ok(Some(recipe)) => HttpResponse::Ok().json(recipe),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Recipe error for product {}: {:?}", dish_product_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[delete("/api/recipes/{dish_product_id}")]
+async fn delete_recipe(state: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
+    let dish_product_id = path.into_inner();
+    match recipes::delete_recipe(&state.pool, dish_product_id).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({ "status": "success" })),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Deleting recipe for product {} failed: {:?}", dish_product_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/ingredient_requirements")]
+async fn get_ingredient_requirements(
+    state: web::Data<AppState>,
+    query: web::Query<recipes::RequirementOptions>,
+) -> impl Responder {
+    match recipes::get_ingredient_requirements(&state.pool, &query).await {
+        Ok(requirements) => HttpResponse::Ok().json(requirements),
+        Err(e) => {
+            error!("Ingredient requirements error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/replenishment")]
+async fn get_replenishment(
+    state: web::Data<AppState>,
+    query: web::Query<replenishment::ReplenishmentOptions>,
+) -> impl Responder {
+    match replenishment::get_replenishment(&state.pool, &state.replenishment_config, &query).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response("Replenishment error", e),
+    }
+}
+
+#[get("/api/calendar")]
+async fn get_calendar(
+    state: web::Data<AppState>,
+    query: web::Query<calendar::CalendarQuery>,
+) -> impl Responder {
+    match calendar::get_calendar(&state.pool, &query).await {
+        Ok(days) => HttpResponse::Ok().json(days),
+        Err(e) => error_response("Calendar error", e),
+    }
+}
+
+#[get("/api/calendar/events")]
+async fn get_calendar_events(state: web::Data<AppState>) -> impl Responder {
+    match calendar::get_events(&state.pool).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            error!("Calendar events error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/calendar/events")]
+async fn save_calendar_events(
+    state: web::Data<AppState>,
+    events: web::Json<Vec<calendar::NewEvent>>,
+) -> impl Responder {
+    match calendar::save_events(&state.pool, &events).await {
+        Ok(saved) => HttpResponse::Ok().json(saved),
+        Err(e) => error_response("Saving calendar events failed", e),
+    }
+}
+
+#[delete("/api/calendar/events/{id}")]
+async fn delete_calendar_event(state: web::Data<AppState>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match calendar::delete_event(&state.pool, id).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({ "status": "success" })),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Deleting calendar event {} failed: {:?}", id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/calendar/uplift")]
+async fn get_calendar_uplift(
+    state: web::Data<AppState>,
+    query: web::Query<calendar::UpliftQuery>,
+) -> impl Responder {
+    match calendar::get_uplift_report(&state.pool, &query).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Holiday uplift error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/stock/levels")]
+async fn get_stock_levels(
+    state: web::Data<AppState>,
+    query: web::Query<stock_movements::LevelQuery>,
+) -> impl Responder {
+    match stock_movements::get_stock_levels(&state.pool, &query).await {
+        Ok(levels) => HttpResponse::Ok().json(levels),
+        Err(e) => {
+            error!("Stock levels error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/stock/variance")]
+async fn get_stock_variance(
+    state: web::Data<AppState>,
+    query: web::Query<stock_movements::VarianceQuery>,
+) -> impl Responder {
+    match stock_movements::get_stock_variance(&state.pool, &query).await {
+        Ok(variances) => HttpResponse::Ok().json(variances),
+        Err(e) => error_response("Stock variance error", e),
+    }
+}
+
+#[get("/api/stock/{product_id}/history")]
+async fn get_stock_history(
+    state: web::Data<AppState>,
+    path: web::Path<i32>,
+    query: web::Query<stock_movements::HistoryQuery>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    match stock_movements::get_stock_history(&state.pool, product_id, &query).await {
+        Ok(movements) => HttpResponse::Ok().json(movements),
+        Err(e) => {
+            error!("Stock history error for product {}: {:?}", product_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/products/{product_id}/shelf_life")]
+async fn set_shelf_life(
+    state: web::Data<AppState>,
+    path: web::Path<i32>,
+    shelf_life: web::Json<waste::ShelfLife>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    match waste::set_shelf_life(&state.pool, product_id, &shelf_life).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({ "status": "success" })),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => error_response(
+            &format!("Setting shelf life for product {} failed", product_id),
+            e,
+        ),
+    }
+}
+
+#[get("/api/perishables")]
+async fn get_perishables(state: web::Data<AppState>) -> impl Responder {
+    match waste::get_perishables(&state.pool).await {
+        Ok(products) => HttpResponse::Ok().json(products),
+        Err(e) => {
+            error!("Perishables error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/waste")]
+async fn record_waste(state: web::Data<AppState>, new_waste: web::Json<waste::NewWaste>) -> impl Responder {
+    match waste::record_waste(&state.pool, &new_waste).await {
+        Ok(record) => HttpResponse::Ok().json(record),
+        Err(e) => error_response("Recording waste failed", e),
+    }
+}
+
+#[get("/api/waste")]
+async fn get_waste(state: web::Data<AppState>, query: web::Query<waste::WasteQuery>) -> impl Responder {
+    match waste::get_waste(&state.pool, &query).await {
+        Ok(records) => HttpResponse::Ok().json(records),
+        Err(e) => {
+            error!("Waste records error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/waste/report")]
+async fn get_waste_report(
+    state: web::Data<AppState>,
+    query: web::Query<waste::WasteQuery>,
+) -> impl Responder {
+    match waste::get_waste_report(&state.pool, &query).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response("Waste report error", e),
+    }
+}
+
+#[get("/api/alerts/stock")]
+async fn get_stock_alerts(
+    state: web::Data<AppState>,
+    query: web::Query<stock_alerts::AlertQuery>,
+) -> impl Responder {
+    match stock_alerts::get_stock_alerts(&state.pool, &query).await {
+        Ok(alerts) => HttpResponse::Ok().json(alerts),
+        Err(e) => {
+            error!("Stock alerts error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/forecast/{product_id}")]
+async fn get_product_forecast(
+    state: web::Data<AppState>,
+    path: web::Path<i32>,
+    query: web::Query<forecasting::ForecastOptions>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    match forecasting::get_product_forecast(&state.pool, product_id, &query).await {
+        Ok(Some(forecast)) => HttpResponse::Ok().json(forecast),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!("Demand forecast error for product {}: {:?}", product_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/forecast_accuracy")]
+async fn get_forecast_accuracy(
+    state: web::Data<AppState>,
+    query: web::Query<forecast_accuracy::AccuracyQuery>,
+) -> impl Responder {
+    match forecast_accuracy::get_forecast_accuracy(&state.pool, &query).await {
+        Ok(accuracy) => HttpResponse::Ok().json(accuracy),
+        Err(e) => {
+            error!("Forecast accuracy error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/api/forecast_accuracy/backtest")]
+async fn run_forecast_backtest(
+    state: web::Data<AppState>,
+    query: web::Query<forecast_accuracy::BacktestOptions>,
+) -> impl Responder {
+    match forecast_accuracy::run_backtests(&state.pool, &query).await {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(e) => {
+            error!("Forecast backtest failed: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/api/forecasts")]
+async fn get_demand_forecasts(
+    state: web::Data<AppState>,
+    query: web::Query<forecasting::ForecastOptions>,
+) -> impl Responder {
+    match forecasting::get_demand_forecasts(&state.pool, &query).await {
+        Ok(forecasts) => HttpResponse::Ok().json(forecasts),
+        Err(e) => {
+            error!("Demand forecast error: {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 #[get("/api/trending_recipes")]
 async fn get_trending_recipes(state: web::Data<AppState>) -> impl Responder {
     match trend_discovery::get_trending_recipes(&state.pool).await {
@@ -130,6 +897,15 @@ async fn main() -> anyhow::Result<()> {
         .context("Failed to run migrations")?;
     info!("Migrations applied successfully.");
 
+    // --- Load user-defined calendar events, if a file is configured ---
+    if let Ok(path) = env::var("CALENDAR_EVENTS_FILE") {
+        if !path.trim().is_empty() {
+            if let Err(e) = calendar::load_events_file(&pg_pool, path.trim()).await {
+                error!("Loading calendar events failed: {:?}", e);
+            }
+        }
+    }
+
     // --- Connect to MySQL (Optional for Demo) ---
     let mysql_dsn = env::var("MYSQL_DSN").context("MYSQL_DSN must be set")?;
     let mysql_pool = match MySqlPool::connect(&mysql_dsn).await {
@@ -147,34 +923,62 @@ async fn main() -> anyhow::Result<()> {
 
     // --- Train the recommendation model at startup ---
     info!("Training recommendation model on server startup...");
-    let recommendation_cache = match recommendations::train_and_cache_recommendations(&pg_pool).await {
+    let recommendation_config = recommendations::RecommendationConfig::from_env();
+    let recommendation_cache = match recommendations::train_and_cache_recommendations(&pg_pool, &recommendation_config).await {
         Ok(cache) => {
             info!("Recommendation model is ready with {} items cached.", cache.len());
+            spawn_model_evaluation(
+                pg_pool.clone(),
+                recommendation_config.clone(),
+                cache.primary.version,
+            );
             cache
         }
         Err(e) => {
             error!("Failed to train recommendation model: {}. Using empty cache.", e);
-            HashMap::new()
+            recommendations::ModelStore::default()
         }
     };
 
+    // --- Precompute demand forecasts now and after every sync that brings in sales ---
+    let replenishment_config = replenishment::ReplenishmentConfig::from_env();
+    let forecasts_stale = Arc::new(Notify::new());
+    spawn_forecast_refresher(pg_pool.clone(), replenishment_config.clone(), forecasts_stale.clone());
+
     // --- Spawn the Market Intelligence Agent ---
     let agent_pool = pg_pool.clone();
     tokio::spawn(async move {
         agent::scraper::run_market_agent(agent_pool).await;
     });
 
+    // --- Create the application state ---
+    let app_state = web::Data::new(AppState {
+        pool: pg_pool.clone(),
+        recommendation_cache: RwLock::new(recommendation_cache),
+        recommendation_config,
+        replenishment_config,
+        retrain_requested: Arc::new(Notify::new()),
+    });
+    spawn_model_retrainer(app_state.clone());
+
     // --- Spawn the periodic database sync task ---
     if let Some(mysql_pool) = mysql_pool {
         let background_pg_pool = pg_pool.clone();
         let background_mysql_pool = mysql_pool.clone();
+        let sales_synced = Arc::new(Notify::new());
+        spawn_model_refresher(app_state.clone(), sales_synced.clone());
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(1800)); // 30 minutes
             loop {
                 interval.tick().await;
                 info!("Running periodic database synchronization...");
-                if let Err(e) = sync::process_changes(&background_mysql_pool, &background_pg_pool).await {
-                    error!("Error during periodic sync: {:?}", e);
+                match sync::process_changes(&background_mysql_pool, &background_pg_pool).await {
+                    Ok(summary) if summary.has_new_sales() => {
+                        sales_synced.notify_one();
+                        forecasts_stale.notify_one();
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Error during periodic sync: {:?}", e),
                 }
                 info!("Synchronization check complete.");
             }
@@ -183,12 +987,6 @@ async fn main() -> anyhow::Result<()> {
         warn!("MySQL pool not available. Periodic sync is disabled.");
     }
 
-    // --- Create the application state ---
-    let app_state = web::Data::new(AppState {
-        pool: pg_pool.clone(),
-        recommendation_cache: RwLock::new(recommendation_cache),
-    });
-
 
     info!("Starting Actix web server at http://127.0.0.1:8080");
     HttpServer::new(move || {
@@ -196,8 +994,44 @@ async fn main() -> anyhow::Result<()> {
             .app_data(app_state.clone())
             .service(health)
             .service(get_recommendations)
+            .service(get_customer_recommendations)
+            .service(get_next_visit_suggestions)
+            .service(get_sequential_patterns)
+            .service(record_recommendation_impressions)
+            .service(get_recommendation_conversions)
             .service(retrain_model)
+            .service(get_experiments)
+            .service(create_experiment)
+            .service(stop_experiment)
+            .service(get_experiment_results)
+            .service(get_recommendation_evaluations)
+            .service(get_bundles)
+            .service(get_substitutes)
             .service(get_stock_optimization)
+            .service(get_product_forecast)
+            .service(get_demand_forecasts)
+            .service(get_forecast_accuracy)
+            .service(run_forecast_backtest)
+            .service(get_replenishment)
+            .service(get_stock_alerts)
+            .service(get_stock_levels)
+            .service(get_stock_variance)
+            .service(get_stock_history)
+            .service(set_shelf_life)
+            .service(get_perishables)
+            .service(record_waste)
+            .service(get_waste)
+            .service(get_waste_report)
+            .service(get_calendar)
+            .service(get_calendar_events)
+            .service(save_calendar_events)
+            .service(delete_calendar_event)
+            .service(get_calendar_uplift)
+            .service(get_recipes)
+            .service(save_recipe)
+            .service(get_recipe)
+            .service(delete_recipe)
+            .service(get_ingredient_requirements)
             .service(get_trending_recipes)
             .service(get_market_intelligence)
     })
@@ -12,12 +12,19 @@ struct MySqlProduct {
     #[sqlx(rename = "sellingprice")]
     selling_price: String,
     current_stock: String,
+    restock_level: i32,
+    #[sqlx(rename = "cost_price_excl")]
+    cost_price: String,
+    unit: String,
+    restock_quantity: i32,
+    supplier: String,
+    inventory_type: String,
 }
 
 pub async fn migrate_products(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Result<(), sqlx::Error> {
     println!("ðŸ“– Migrating products...");
 
-    let mysql_products = sqlx::query_as::<_, MySqlProduct>("SELECT product_id, product_code, productname, department, category, sellingprice, current_stock FROM products")
+    let mysql_products = sqlx::query_as::<_, MySqlProduct>("SELECT product_id, product_code, productname, department, category, sellingprice, current_stock, restock_level, cost_price_excl, unit, restock_quantity, supplier, inventory_type FROM products")
         .fetch_all(mysql_pool)
         .await?;
 
@@ -36,6 +43,12 @@ pub async fn migrate_products(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Resul
                 category: p.category,
                 selling_price,
                 current_stock,
+                restock_level: p.restock_level as f32,
+                cost_price: p.cost_price.trim().parse::<f32>().unwrap_or(0.0),
+                unit: p.unit.clone(),
+                restock_quantity: p.restock_quantity as f32,
+                supplier: p.supplier.clone(),
+                inventory_type: p.inventory_type.clone(),
             }
         })
         .collect();
@@ -43,15 +56,21 @@ pub async fn migrate_products(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Resul
     let mut success_count = 0;
     for product in &products {
         let result = sqlx::query(
-            "INSERT INTO products (product_id, product_code, name, department, category, selling_price, current_stock)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "INSERT INTO products (product_id, product_code, name, department, category, selling_price, current_stock, restock_level, cost_price, unit, restock_quantity, supplier, inventory_type)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
              ON CONFLICT (product_id) DO UPDATE SET
                 product_code = EXCLUDED.product_code,
                 name = EXCLUDED.name,
                 department = EXCLUDED.department,
                 category = EXCLUDED.category,
                 selling_price = EXCLUDED.selling_price,
-                current_stock = EXCLUDED.current_stock;",
+                current_stock = EXCLUDED.current_stock,
+                restock_level = EXCLUDED.restock_level,
+                cost_price = EXCLUDED.cost_price,
+                unit = EXCLUDED.unit,
+                restock_quantity = EXCLUDED.restock_quantity,
+                supplier = EXCLUDED.supplier,
+                inventory_type = EXCLUDED.inventory_type;",
         )
         .bind(product.product_id)
         .bind(&product.product_code)
@@ -60,6 +79,12 @@ pub async fn migrate_products(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Resul
         .bind(&product.category)
         .bind(product.selling_price)
         .bind(product.current_stock)
+        .bind(product.restock_level)
+        .bind(product.cost_price)
+        .bind(&product.unit)
+        .bind(product.restock_quantity)
+        .bind(&product.supplier)
+        .bind(&product.inventory_type)
         .execute(pg_pool)
         .await;
 
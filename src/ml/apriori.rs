@@ -6,14 +6,19 @@ pub struct Rule<T: Eq + Hash> {
     pub lhs: Vec<T>,
     pub rhs: Vec<T>,
     pub confidence: f64,
+    /// Confidence relative to how often the rhs is bought anyway.
+    pub lift: f64,
 }
 
+/// Each transaction contributes its weight to support instead of 1, e.g. to let
+/// recent baskets count for more than old ones. Pass 1.0 everywhere for plain counts.
 pub fn apriori<T: Eq + Hash + Clone + Ord>(
     transactions: &[Vec<T>],
+    weights: &[f64],
     min_support: f64,
     min_confidence: f64,
 ) -> Vec<Rule<T>> {
-    let frequent_itemsets = get_frequent_itemsets(transactions, min_support);
+    let frequent_itemsets = get_frequent_itemsets(transactions, weights, min_support);
     let mut rules = Vec::new();
 
     for (itemset, &itemset_support) in &frequent_itemsets {
@@ -32,10 +37,15 @@ pub fn apriori<T: Eq + Hash + Clone + Ord>(
                             .cloned()
                             .collect();
                         if !rhs.is_empty() {
+                            let lift = frequent_itemsets
+                                .get(&rhs)
+                                .filter(|support| **support > 0.0)
+                                .map_or(1.0, |support| confidence / support);
                             rules.push(Rule {
                                 lhs,
                                 rhs,
                                 confidence,
+                                lift,
                             });
                         }
                     }
@@ -46,25 +56,27 @@ pub fn apriori<T: Eq + Hash + Clone + Ord>(
     rules
 }
 
-fn get_frequent_itemsets<T: Eq + Hash + Clone + Ord>(
+pub fn get_frequent_itemsets<T: Eq + Hash + Clone + Ord>(
     transactions: &[Vec<T>],
+    weights: &[f64],
     min_support: f64,
 ) -> HashMap<Vec<T>, f64> {
-    let mut item_counts: HashMap<T, usize> = HashMap::new();
-    for transaction in transactions {
-        for item in transaction {
-            *item_counts.entry(item.clone()).or_insert(0) += 1;
+    let mut item_counts: HashMap<T, f64> = HashMap::new();
+    for (transaction, weight) in transactions.iter().zip(weights) {
+        let unique_items: HashSet<&T> = transaction.iter().collect();
+        for item in unique_items {
+            *item_counts.entry(item.clone()).or_insert(0.0) += weight;
         }
     }
 
-    let num_transactions = transactions.len() as f64;
+    let num_transactions: f64 = weights.iter().take(transactions.len()).sum();
     let mut frequent_itemsets: HashMap<Vec<T>, f64> = HashMap::new();
 
     // Level 1 frequent itemsets
     let l1: HashSet<Vec<T>> = item_counts
         .into_iter()
         .filter_map(|(item, count)| {
-            let support = count as f64 / num_transactions;
+            let support = count / num_transactions;
             if support >= min_support {
                 let itemset = vec![item];
                 frequent_itemsets.insert(itemset.clone(), support);
@@ -89,16 +101,16 @@ fn get_frequent_itemsets<T: Eq + Hash + Clone + Ord>(
             .collect();
 
         for mut candidate in ck {
-            let mut count = 0;
+            let mut count = 0.0;
             let candidate_set: HashSet<_> = candidate.iter().cloned().collect();
             
-            for t_set in &transaction_sets {
+            for (t_set, weight) in transaction_sets.iter().zip(weights) {
                 if candidate_set.is_subset(t_set) {
-                    count += 1;
+                    count += weight;
                 }
             }
 
-            let support = count as f64 / num_transactions;
+            let support = count / num_transactions;
             if support >= min_support {
                 candidate.sort();
                 frequent_itemsets.insert(candidate.clone(), support);
@@ -152,3 +164,46 @@ fn combinations<T: Clone + Ord>(items: &[T], k: usize) -> Vec<Vec<T>> {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frequent_pairs(itemsets: &HashMap<Vec<i32>, f64>) -> Vec<Vec<i32>> {
+        let mut pairs: Vec<_> = itemsets.keys().filter(|s| s.len() == 2).cloned().collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn weights_change_support_and_frequent_pairs() {
+        let transactions = vec![vec![1, 2], vec![1, 3], vec![3, 1]];
+
+        let plain = get_frequent_itemsets(&transactions, &[1.0, 1.0, 1.0], 0.5);
+        assert_eq!(frequent_pairs(&plain), vec![vec![1, 3]]);
+        assert!((plain[&vec![1, 3]] - 2.0 / 3.0).abs() < 1e-9);
+
+        // The first basket outweighs the other two together.
+        let weighted = get_frequent_itemsets(&transactions, &[3.0, 1.0, 1.0], 0.5);
+        assert_eq!(frequent_pairs(&weighted), vec![vec![1, 2]]);
+        assert!((weighted[&vec![1, 2]] - 0.6).abs() < 1e-9);
+        assert!((weighted[&vec![1]] - 1.0).abs() < 1e-9);
+        assert!(!weighted.contains_key(&vec![3]));
+    }
+
+    #[test]
+    fn weighted_rules_use_weighted_confidence() {
+        let transactions = vec![vec![1, 2], vec![1, 3], vec![3, 1]];
+        let rules = apriori(&transactions, &[3.0, 1.0, 1.0], 0.5, 0.0);
+
+        let confidence = |lhs: i32, rhs: i32| {
+            rules
+                .iter()
+                .find(|r| r.lhs == vec![lhs] && r.rhs == vec![rhs])
+                .map(|r| r.confidence)
+        };
+        assert!((confidence(2, 1).unwrap() - 1.0).abs() < 1e-9);
+        assert!((confidence(1, 2).unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(confidence(1, 3), None);
+    }
+}
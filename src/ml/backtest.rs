@@ -0,0 +1,165 @@
+use super::forecasting::Method;
+use super::regressors::{self, DayFeatures};
+
+/// Out-of-sample errors of one method over a rolling-origin backtest.
+#[derive(Debug, Clone)]
+pub struct Accuracy {
+    pub method: Method,
+    /// Mean absolute percentage error over days with sales only; `None` when
+    /// no forecast day had any.
+    pub mape: Option<f64>,
+    /// Symmetric MAPE, 0-200, with days where both sides are zero counting as exact.
+    pub smape: f64,
+    /// Mean absolute error scaled by the in-sample error of a one-day naive
+    /// forecast; below 1 beats "same as yesterday".
+    pub mase: Option<f64>,
+    /// Mean of forecast minus actual in units; positive means over-forecasting.
+    pub bias: f64,
+    pub forecasts: usize,
+    pub origins: usize,
+}
+
+/// Refits `method` at several origins going back `step` days at a time, each
+/// time forecasting the next `horizon` days from the history before it. Every
+/// origin keeps at least two seasons of training data so all methods are
+/// compared on the same days. Calendar `features` for the series, if any,
+/// are used as regressors. `None` when the series is too short for one.
+pub fn rolling_origin(
+    series: &[f64],
+    features: &[DayFeatures],
+    season: usize,
+    method: Method,
+    horizon: usize,
+    origins: usize,
+    step: usize,
+) -> Option<Accuracy> {
+    let horizon = horizon.max(1);
+    let min_train = (2 * season).max(method.min_history(season)).max(2);
+    let last_origin = series.len().checked_sub(horizon)?;
+
+    let mut absolute_percentage = Vec::new();
+    let mut symmetric = Vec::new();
+    let mut scaled = Vec::new();
+    let mut errors = Vec::new();
+    let mut used = 0;
+    for k in 0..origins {
+        let Some(origin) = last_origin.checked_sub(k * step.max(1)) else {
+            break;
+        };
+        if origin < min_train {
+            break;
+        }
+        let train = &series[..origin];
+        let (forecast, _) = regressors::forecast(train, features, season, horizon, Some(method));
+        let naive_mae = train.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>()
+            / (train.len() - 1) as f64;
+
+        for (predicted, actual) in forecast.points.iter().zip(&series[origin..origin + horizon]) {
+            let error = predicted - actual;
+            errors.push(error);
+            if *actual > 0.0 {
+                absolute_percentage.push(100.0 * error.abs() / actual);
+            }
+            let denominator = predicted.abs() + actual.abs();
+            symmetric.push(if denominator > 0.0 {
+                200.0 * error.abs() / denominator
+            } else {
+                0.0
+            });
+            if naive_mae > 0.0 {
+                scaled.push(error.abs() / naive_mae);
+            }
+        }
+        used += 1;
+    }
+
+    if used == 0 {
+        return None;
+    }
+    Some(Accuracy {
+        method,
+        mape: mean(&absolute_percentage),
+        smape: mean(&symmetric).unwrap_or(0.0),
+        mase: mean(&scaled),
+        bias: mean(&errors).unwrap_or(0.0),
+        forecasts: errors.len(),
+        origins: used,
+    })
+}
+
+/// The most accurate method: lowest MASE, or lowest sMAPE when MASE is not
+/// available (a series that never changed).
+pub fn best(accuracies: &[Accuracy]) -> Option<&Accuracy> {
+    accuracies.iter().min_by(|a, b| match (a.mase, b.mase) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.smape.total_cmp(&b.smape),
+    })
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ml::forecasting::WEEK;
+
+    fn weekly(weeks: usize) -> Vec<f64> {
+        let week = [10.0, 12.0, 9.0, 11.0, 20.0, 30.0, 25.0];
+        week.iter().copied().cycle().take(weeks * WEEK).collect()
+    }
+
+    fn accuracy(method: Method, mase: Option<f64>, smape: f64) -> Accuracy {
+        Accuracy {
+            method,
+            mape: None,
+            smape,
+            mase,
+            bias: 0.0,
+            forecasts: 1,
+            origins: 1,
+        }
+    }
+
+    #[test]
+    fn seasonal_naive_is_exact_on_a_repeating_week() {
+        let series = weekly(8);
+        let accuracy = rolling_origin(&series, &[], WEEK, Method::SeasonalNaive, WEEK, 3, WEEK).unwrap();
+        assert_eq!(accuracy.origins, 3);
+        assert_eq!(accuracy.forecasts, 3 * WEEK);
+        assert_eq!(accuracy.mase, Some(0.0));
+        assert_eq!(accuracy.bias, 0.0);
+
+        let naive = rolling_origin(&series, &[], WEEK, Method::Naive, WEEK, 3, WEEK).unwrap();
+        assert!(naive.mase.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn origins_stop_at_two_seasons_of_training_data() {
+        let series = weekly(4);
+        let accuracy = rolling_origin(&series, &[], WEEK, Method::Naive, WEEK, 5, WEEK).unwrap();
+        assert_eq!(accuracy.origins, 2);
+        assert!(rolling_origin(&weekly(2), &[], WEEK, Method::Naive, WEEK, 5, WEEK).is_none());
+        assert!(rolling_origin(&series[..3], &[], WEEK, Method::Naive, WEEK, 5, WEEK).is_none());
+    }
+
+    #[test]
+    fn best_prefers_lowest_mase_then_smape() {
+        let accuracies = [
+            accuracy(Method::Naive, Some(1.2), 10.0),
+            accuracy(Method::SeasonalNaive, Some(0.8), 30.0),
+            accuracy(Method::MovingAverage, None, 5.0),
+        ];
+        assert_eq!(best(&accuracies).unwrap().method, Method::SeasonalNaive);
+
+        let unscaled = [
+            accuracy(Method::Naive, None, 10.0),
+            accuracy(Method::MovingAverage, None, 5.0),
+        ];
+        assert_eq!(best(&unscaled).unwrap().method, Method::MovingAverage);
+        assert!(best(&[]).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Item-item cosine similarity over implicit feedback. Each history is one
+/// customer's purchased items with a weight (e.g. log-scaled purchase count);
+/// items are expected to appear at most once per history.
+pub fn item_similarities<T: Eq + Hash + Clone + Ord>(
+    histories: &[Vec<(T, f64)>],
+    top_k: usize,
+) -> HashMap<T, Vec<(T, f64)>> {
+    let mut norms: HashMap<T, f64> = HashMap::new();
+    let mut dot_products: HashMap<(T, T), f64> = HashMap::new();
+
+    for history in histories {
+        for (i, (a, weight_a)) in history.iter().enumerate() {
+            *norms.entry(a.clone()).or_insert(0.0) += weight_a * weight_a;
+            for (b, weight_b) in &history[i + 1..] {
+                if a == b {
+                    continue;
+                }
+                let key = if a < b {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                };
+                *dot_products.entry(key).or_insert(0.0) += weight_a * weight_b;
+            }
+        }
+    }
+
+    let mut similarities: HashMap<T, Vec<(T, f64)>> = HashMap::new();
+    for ((a, b), dot) in dot_products {
+        let denominator = (norms[&a] * norms[&b]).sqrt();
+        if denominator <= 0.0 {
+            continue;
+        }
+        let similarity = dot / denominator;
+        similarities
+            .entry(a.clone())
+            .or_default()
+            .push((b.clone(), similarity));
+        similarities.entry(b).or_default().push((a, similarity));
+    }
+
+    for neighbours in similarities.values_mut() {
+        neighbours.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(std::cmp::Ordering::Equal));
+        neighbours.truncate(top_k);
+    }
+
+    similarities
+}
+
+/// Scores every item similar to something in `history`, skipping items the
+/// customer already buys.
+pub fn recommend<T: Eq + Hash + Clone>(
+    similarities: &HashMap<T, Vec<(T, f64)>>,
+    history: &[(T, f64)],
+    limit: usize,
+) -> Vec<(T, f64)> {
+    let owned: HashSet<&T> = history.iter().map(|(item, _)| item).collect();
+    let mut scores: HashMap<T, f64> = HashMap::new();
+
+    for (item, weight) in history {
+        if let Some(neighbours) = similarities.get(item) {
+            for (other, similarity) in neighbours {
+                if !owned.contains(other) {
+                    *scores.entry(other.clone()).or_insert(0.0) += weight * similarity;
+                }
+            }
+        }
+    }
+
+    let mut ranked: Vec<(T, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histories() -> Vec<Vec<(i32, f64)>> {
+        vec![
+            vec![(1, 1.0), (2, 1.0)],
+            vec![(1, 1.0), (2, 1.0), (3, 1.0)],
+            vec![(3, 2.0)],
+        ]
+    }
+
+    fn similarity(similarities: &HashMap<i32, Vec<(i32, f64)>>, a: i32, b: i32) -> f64 {
+        similarities[&a].iter().find(|(item, _)| *item == b).unwrap().1
+    }
+
+    #[test]
+    fn similarities_are_weighted_cosines() {
+        let similarities = item_similarities(&histories(), 5);
+        // Items 1 and 2 are always bought together.
+        assert!((similarity(&similarities, 1, 2) - 1.0).abs() < 1e-9);
+        // One shared customer; item 3's norm includes the weight-2 history.
+        let expected = 1.0 / (2.0f64 * 5.0).sqrt();
+        assert!((similarity(&similarities, 1, 3) - expected).abs() < 1e-9);
+        assert!((similarity(&similarities, 3, 2) - expected).abs() < 1e-9);
+
+        let top = item_similarities(&histories(), 1);
+        assert_eq!(top[&1].len(), 1);
+        assert_eq!(top[&1][0].0, 2);
+    }
+
+    #[test]
+    fn recommendations_sum_weighted_similarities_and_skip_owned_items() {
+        let similarities = item_similarities(&histories(), 5);
+
+        let ranked = recommend(&similarities, &[(1, 2.0)], 10);
+        let items: Vec<i32> = ranked.iter().map(|(item, _)| *item).collect();
+        assert_eq!(items, vec![2, 3]);
+        assert!((ranked[0].1 - 2.0).abs() < 1e-9);
+        assert!((ranked[1].1 - 2.0 / 10f64.sqrt()).abs() < 1e-9);
+
+        let ranked = recommend(&similarities, &[(1, 1.0), (2, 1.0)], 10);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, 3);
+        assert!((ranked[0].1 - 2.0 / 10f64.sqrt()).abs() < 1e-9);
+
+        assert_eq!(recommend(&similarities, &[(1, 2.0)], 1).len(), 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+// Relative weight of each attribute before IDF weighting.
+const NAME_WEIGHT: f64 = 1.0;
+const CATEGORY_WEIGHT: f64 = 2.0;
+const DEPARTMENT_WEIGHT: f64 = 0.5;
+const UNIT_WEIGHT: f64 = 1.0;
+const PRICE_WEIGHT: f64 = 1.0;
+// Each price band is 50% wider than the one below it.
+const PRICE_BAND_RATIO: f64 = 1.5;
+
+/// Weighted features describing a product by its attributes rather than its sales.
+pub fn product_features(
+    name: Option<&str>,
+    category: Option<&str>,
+    department: Option<&str>,
+    unit: Option<&str>,
+    price: Option<f64>,
+) -> Vec<(String, f64)> {
+    let mut features: Vec<(String, f64)> = name
+        .map(tokenize)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|token| (format!("name:{}", token), NAME_WEIGHT))
+        .collect();
+
+    let mut push = |prefix: &str, value: Option<&str>, weight: f64| {
+        if let Some(value) = value.map(|v| v.trim().to_lowercase()).filter(|v| !v.is_empty()) {
+            features.push((format!("{}:{}", prefix, value), weight));
+        }
+    };
+    push("category", category, CATEGORY_WEIGHT);
+    push("department", department, DEPARTMENT_WEIGHT);
+    push("unit", unit, UNIT_WEIGHT);
+
+    // Neighbouring bands count for half, so 95 and 105 are still similar.
+    if let Some(band) = price.filter(|p| *p > 0.0).map(price_band) {
+        features.push((format!("price:{}", band), PRICE_WEIGHT));
+        features.push((format!("price:{}", band - 1), PRICE_WEIGHT / 2.0));
+        features.push((format!("price:{}", band + 1), PRICE_WEIGHT / 2.0));
+    }
+
+    features
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() > 1 && !token.chars().all(|c| c.is_numeric()))
+        .map(|token| token.to_lowercase())
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+fn price_band(price: f64) -> i64 {
+    (price.ln() / PRICE_BAND_RATIO.ln()).floor() as i64
+}
+
+/// For every item in `queries`, the `top_k` most similar items in `candidates`
+/// by IDF-weighted cosine similarity of their features. Features shared by
+/// most of the catalog (e.g. the only department) carry little weight.
+pub fn similarities<T: Eq + Hash + Clone>(
+    items: &[(T, Vec<(String, f64)>)],
+    queries: &HashSet<T>,
+    candidates: &HashSet<T>,
+    top_k: usize,
+) -> HashMap<T, Vec<(T, f64)>> {
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for (_, features) in items {
+        let distinct: HashSet<&str> = features.iter().map(|(f, _)| f.as_str()).collect();
+        for feature in distinct {
+            *document_frequency.entry(feature).or_insert(0) += 1;
+        }
+    }
+    let documents = items.len() as f64;
+
+    let vectorize = |features: &[(String, f64)]| -> (HashMap<String, f64>, f64) {
+        let mut vector: HashMap<String, f64> = HashMap::new();
+        for (feature, weight) in features {
+            let df = document_frequency.get(feature.as_str()).copied().unwrap_or(1) as f64;
+            let idf = (documents / df).ln() + 1.0;
+            *vector.entry(feature.clone()).or_insert(0.0) += weight * idf;
+        }
+        let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+        (vector, norm)
+    };
+    let candidate_vectors: Vec<(&T, HashMap<String, f64>, f64)> = items
+        .iter()
+        .filter(|(item, _)| candidates.contains(item))
+        .map(|(item, features)| {
+            let (vector, norm) = vectorize(features);
+            (item, vector, norm)
+        })
+        .collect();
+
+    let mut result = HashMap::new();
+    for (item, features) in items.iter().filter(|(item, _)| queries.contains(item)) {
+        let (vector, norm) = vectorize(features);
+        if norm <= 0.0 {
+            continue;
+        }
+        let mut neighbours: Vec<(T, f64)> = candidate_vectors
+            .iter()
+            .filter(|(candidate, _, candidate_norm)| *candidate != item && *candidate_norm > 0.0)
+            .filter_map(|(candidate, candidate_vector, candidate_norm)| {
+                let dot: f64 = vector
+                    .iter()
+                    .filter_map(|(feature, w)| Some(w * candidate_vector.get(feature)?))
+                    .sum();
+                (dot > 0.0).then(|| ((*candidate).clone(), dot / (norm * candidate_norm)))
+            })
+            .collect();
+        neighbours.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(std::cmp::Ordering::Equal));
+        neighbours.truncate(top_k);
+        if !neighbours.is_empty() {
+            result.insert(item.clone(), neighbours);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(names: &[&str]) -> Vec<(String, f64)> {
+        names.iter().map(|name| (name.to_string(), 1.0)).collect()
+    }
+
+    #[test]
+    fn tokens_skip_single_characters_and_numbers() {
+        assert_eq!(tokenize("Oat Milk 1L, oat"), vec!["1l", "milk", "oat"]);
+        assert!(tokenize("2 x 500").is_empty());
+    }
+
+    #[test]
+    fn features_cover_attributes_and_neighbouring_price_bands() {
+        let features =
+            product_features(Some("Flat White"), Some(" Coffee "), None, Some(""), Some(3.0));
+        let band = price_band(3.0);
+        let expected = [
+            ("name:flat".to_string(), NAME_WEIGHT),
+            ("name:white".to_string(), NAME_WEIGHT),
+            ("category:coffee".to_string(), CATEGORY_WEIGHT),
+            (format!("price:{}", band), PRICE_WEIGHT),
+            (format!("price:{}", band - 1), PRICE_WEIGHT / 2.0),
+            (format!("price:{}", band + 1), PRICE_WEIGHT / 2.0),
+        ];
+        assert_eq!(features, expected);
+        assert_eq!(price_band(100.0) + 1, price_band(150.0));
+    }
+
+    #[test]
+    fn similarities_are_idf_weighted_cosines() {
+        let items = vec![
+            (1, features(&["a", "b"])),
+            (2, features(&["a", "b"])),
+            (3, features(&["a", "c"])),
+            (4, features(&["d"])),
+        ];
+        let all: HashSet<i32> = (1..=4).collect();
+        let result = similarities(&items, &all, &all, 5);
+
+        let idf = |df: f64| (4.0 / df).ln() + 1.0;
+        let (a, b, c) = (idf(3.0), idf(2.0), idf(1.0));
+        let expected = a * a / ((a * a + b * b).sqrt() * (a * a + c * c).sqrt());
+        let neighbours = &result[&1];
+        assert_eq!(neighbours.len(), 2);
+        assert_eq!(neighbours[0].0, 2);
+        assert!((neighbours[0].1 - 1.0).abs() < 1e-9);
+        assert_eq!(neighbours[1].0, 3);
+        assert!((neighbours[1].1 - expected).abs() < 1e-9);
+        // Nothing shares a feature with item 4.
+        assert!(!result.contains_key(&4));
+
+        let queries = HashSet::from([3]);
+        let candidates = HashSet::from([1, 4]);
+        let result = similarities(&items, &queries, &candidates, 1);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[&3].iter().map(|(item, _)| *item).collect::<Vec<_>>(), vec![1]);
+    }
+}
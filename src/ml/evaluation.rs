@@ -0,0 +1,165 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A held-out basket with one item hidden: the recommender sees `context`
+/// and is scored on whether it ranks `hidden` in its top k.
+#[derive(Debug, Clone)]
+pub struct HoldoutCase<T> {
+    pub context: Vec<T>,
+    pub hidden: T,
+    pub segment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Metrics {
+    pub k: usize,
+    pub cases: usize,
+    pub hit_rate: f64,
+    pub precision_at_k: f64,
+    pub mrr: f64,
+    pub catalog_coverage: f64,
+    /// Mean popularity percentile (0 = rarest, 1 = best seller) of recommended items.
+    pub popularity_bias: f64,
+}
+
+/// Every distinct item in the basket takes a turn being hidden.
+pub fn leave_one_out<T: Eq + Hash + Clone + Ord>(
+    basket: &[T],
+    segment: Option<String>,
+) -> Vec<HoldoutCase<T>> {
+    let mut items: Vec<T> = basket.to_vec();
+    items.sort();
+    items.dedup();
+    if items.len() < 2 {
+        return Vec::new();
+    }
+
+    items
+        .iter()
+        .map(|hidden| HoldoutCase {
+            context: items.iter().filter(|item| *item != hidden).cloned().collect(),
+            hidden: hidden.clone(),
+            segment: segment.clone(),
+        })
+        .collect()
+}
+
+pub fn evaluate<T, F>(
+    train: &[Vec<T>],
+    cases: &[HoldoutCase<T>],
+    k: usize,
+    mut recommend: F,
+) -> Metrics
+where
+    T: Eq + Hash + Clone,
+    F: FnMut(&HoldoutCase<T>) -> Vec<T>,
+{
+    let popularity = popularity_percentiles(train);
+    let mut catalog: HashSet<T> = popularity.keys().cloned().collect();
+    let mut recommended: HashSet<T> = HashSet::new();
+
+    let mut hits = 0usize;
+    let mut reciprocal_rank_sum = 0.0;
+    let mut popularity_sum = 0.0;
+    let mut recommendation_count = 0usize;
+
+    for case in cases {
+        catalog.insert(case.hidden.clone());
+        catalog.extend(case.context.iter().cloned());
+
+        let ranked: Vec<T> = recommend(case).into_iter().take(k).collect();
+        if let Some(position) = ranked.iter().position(|item| *item == case.hidden) {
+            hits += 1;
+            reciprocal_rank_sum += 1.0 / (position + 1) as f64;
+        }
+        for item in ranked {
+            popularity_sum += popularity.get(&item).copied().unwrap_or(0.0);
+            recommendation_count += 1;
+            recommended.insert(item);
+        }
+    }
+
+    let case_count = cases.len().max(1) as f64;
+    Metrics {
+        k,
+        cases: cases.len(),
+        hit_rate: hits as f64 / case_count,
+        precision_at_k: hits as f64 / (case_count * k.max(1) as f64),
+        mrr: reciprocal_rank_sum / case_count,
+        catalog_coverage: recommended.len() as f64 / catalog.len().max(1) as f64,
+        popularity_bias: popularity_sum / recommendation_count.max(1) as f64,
+    }
+}
+
+fn popularity_percentiles<T: Eq + Hash + Clone>(transactions: &[Vec<T>]) -> HashMap<T, f64> {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for transaction in transactions {
+        for item in transaction {
+            *counts.entry(item.clone()).or_insert(0) += 1;
+        }
+    }
+
+    let mut ranked: Vec<(T, usize)> = counts.into_iter().collect();
+    ranked.sort_by_key(|(_, count)| *count);
+    let denominator = ranked.len().saturating_sub(1).max(1) as f64;
+    ranked
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (item, _))| (item, rank as f64 / denominator))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(context: Vec<i32>, hidden: i32) -> HoldoutCase<i32> {
+        HoldoutCase {
+            context,
+            hidden,
+            segment: None,
+        }
+    }
+
+    #[test]
+    fn leave_one_out_hides_each_distinct_item() {
+        let cases = leave_one_out(&[2, 1, 2], Some("morning".to_string()));
+        let hidden: Vec<(Vec<i32>, i32)> =
+            cases.iter().map(|c| (c.context.clone(), c.hidden)).collect();
+        assert_eq!(hidden, vec![(vec![2], 1), (vec![1], 2)]);
+        assert!(cases.iter().all(|c| c.segment.as_deref() == Some("morning")));
+        assert!(leave_one_out(&[1, 1], None).is_empty());
+    }
+
+    #[test]
+    fn metrics_score_the_top_k_only() {
+        // Item 1 is the best seller, 3 the rarest.
+        let train = vec![vec![1, 2, 3], vec![1, 2], vec![1]];
+        let cases = vec![case(vec![1], 2), case(vec![1], 3), case(vec![2], 4)];
+        let metrics = evaluate(&train, &cases, 2, |c| match c.hidden {
+            2 => vec![3, 2, 5],
+            3 => vec![2, 3],
+            _ => vec![1],
+        });
+
+        assert_eq!((metrics.k, metrics.cases), (2, 3));
+        assert!((metrics.hit_rate - 2.0 / 3.0).abs() < 1e-9);
+        // With one hidden item per case, precision is the hit rate over k.
+        assert!((metrics.precision_at_k - 1.0 / 3.0).abs() < 1e-9);
+        assert!((metrics.mrr - 1.0 / 3.0).abs() < 1e-9);
+        // Items 1 to 3 of the 4 seen; item 5 was ranked past k.
+        assert!((metrics.catalog_coverage - 0.75).abs() < 1e-9);
+        assert!((metrics.popularity_bias - 2.0 / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn metrics_without_cases_are_zero() {
+        let metrics = evaluate::<i32, _>(&[], &[], 5, |_| Vec::new());
+        assert_eq!(metrics.cases, 0);
+        assert_eq!(metrics.hit_rate, 0.0);
+        assert_eq!(metrics.precision_at_k, 0.0);
+        assert_eq!(metrics.mrr, 0.0);
+        assert_eq!(metrics.catalog_coverage, 0.0);
+    }
+}
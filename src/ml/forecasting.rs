@@ -0,0 +1,481 @@
+use serde::{Deserialize, Serialize};
+
+/// Days in the weekly cycle restaurant demand follows.
+pub const WEEK: usize = 7;
+
+// Trend damping, so a few busy days are not extrapolated forever.
+const DAMPING: f64 = 0.9;
+const ALPHAS: [f64; 5] = [0.05, 0.1, 0.2, 0.3, 0.5];
+const BETAS: [f64; 3] = [0.01, 0.05, 0.1];
+const GAMMAS: [f64; 3] = [0.05, 0.1, 0.3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// Average of the days seen so far, for series shorter than a season.
+    Mean,
+    /// Last day's value.
+    Naive,
+    /// Average of the last season.
+    MovingAverage,
+    /// Simple exponential smoothing, no trend or seasonality.
+    ExponentialSmoothing,
+    /// Least-squares straight line through the history.
+    LinearTrend,
+    /// Same day last week.
+    SeasonalNaive,
+    /// Additive Holt-Winters with a damped trend.
+    HoltWinters,
+}
+
+impl Method {
+    /// Every method worth comparing in a backtest.
+    pub const CANDIDATES: [Method; 6] = [
+        Method::Naive,
+        Method::MovingAverage,
+        Method::ExponentialSmoothing,
+        Method::LinearTrend,
+        Method::SeasonalNaive,
+        Method::HoltWinters,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Mean => "mean",
+            Method::Naive => "naive",
+            Method::MovingAverage => "moving_average",
+            Method::ExponentialSmoothing => "exponential_smoothing",
+            Method::LinearTrend => "linear_trend",
+            Method::SeasonalNaive => "seasonal_naive",
+            Method::HoltWinters => "holt_winters",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Method::Mean]
+            .into_iter()
+            .chain(Method::CANDIDATES)
+            .find(|method| method.as_str() == value.trim())
+    }
+
+    /// Shortest series the method can be fitted to.
+    pub fn min_history(&self, season: usize) -> usize {
+        match self {
+            Method::Mean => 0,
+            Method::Naive | Method::MovingAverage => 1,
+            Method::ExponentialSmoothing | Method::LinearTrend => 2,
+            Method::SeasonalNaive => season,
+            Method::HoltWinters => 2 * season,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Forecast {
+    pub method: Method,
+    /// One value per step ahead, never negative.
+    pub points: Vec<f64>,
+    /// Standard deviation of the one-step-ahead errors over the history.
+    pub residual_sd: f64,
+    /// Standard deviation of the forecast error at each step ahead, growing
+    /// with the horizon as far as the method lets errors accumulate.
+    pub step_sd: Vec<f64>,
+}
+
+impl Forecast {
+    /// Central prediction interval at each step, e.g. 0.95 for 95%, assuming
+    /// normal errors. Lower bounds never go below zero.
+    pub fn interval(&self, coverage: f64) -> Vec<(f64, f64)> {
+        let z = normal_quantile(0.5 + coverage.clamp(0.0, 0.999) / 2.0);
+        self.points
+            .iter()
+            .zip(&self.step_sd)
+            .map(|(point, sd)| ((point - z * sd).max(0.0), point + z * sd))
+            .collect()
+    }
+}
+
+/// Forecasts `horizon` steps of a series with a seasonal cycle of `season`
+/// steps. Without a method, Holt-Winters is used once there are two full
+/// seasons to initialise it, seasonal naive once there is one. A method the
+/// series is too short for falls back the same way.
+pub fn forecast(series: &[f64], season: usize, horizon: usize, method: Option<Method>) -> Forecast {
+    let season = season.max(1);
+    let method = [method, Some(Method::HoltWinters), Some(Method::SeasonalNaive)]
+        .into_iter()
+        .flatten()
+        .find(|m| series.len() >= m.min_history(season))
+        .unwrap_or(Method::Mean);
+
+    match method {
+        Method::HoltWinters => holt_winters(series, season, horizon),
+        Method::SeasonalNaive => seasonal_naive(series, season, horizon),
+        Method::Naive => naive(series, horizon),
+        Method::MovingAverage => moving_average(series, season, horizon),
+        Method::ExponentialSmoothing => exponential_smoothing(series, horizon),
+        Method::LinearTrend => linear_trend(series, horizon),
+        Method::Mean => mean(series, horizon),
+    }
+}
+
+fn mean(series: &[f64], horizon: usize) -> Forecast {
+    let average = if series.is_empty() {
+        0.0
+    } else {
+        series.iter().sum::<f64>() / series.len() as f64
+    };
+    let errors: Vec<f64> = series.iter().map(|y| y - average).collect();
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::Mean,
+        points: vec![average.max(0.0); horizon],
+        residual_sd,
+        step_sd: vec![residual_sd; horizon],
+    }
+}
+
+fn naive(series: &[f64], horizon: usize) -> Forecast {
+    let last = series[series.len() - 1];
+    let errors: Vec<f64> = series.windows(2).map(|w| w[1] - w[0]).collect();
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::Naive,
+        points: vec![last.max(0.0); horizon],
+        residual_sd,
+        step_sd: (1..=horizon).map(|h| residual_sd * (h as f64).sqrt()).collect(),
+    }
+}
+
+fn moving_average(series: &[f64], window: usize, horizon: usize) -> Forecast {
+    let window = window.min(series.len());
+    let average = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let errors: Vec<f64> = (window..series.len())
+        .map(|t| series[t] - average(&series[t - window..t]))
+        .collect();
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::MovingAverage,
+        points: vec![average(&series[series.len() - window..]).max(0.0); horizon],
+        residual_sd,
+        step_sd: vec![residual_sd; horizon],
+    }
+}
+
+fn exponential_smoothing(series: &[f64], horizon: usize) -> Forecast {
+    let fit = |alpha: f64| {
+        let mut level = series[0];
+        let mut errors = Vec::with_capacity(series.len() - 1);
+        for y in &series[1..] {
+            errors.push(y - level);
+            level += alpha * (y - level);
+        }
+        (level, errors)
+    };
+    let (alpha, (level, errors)) = ALPHAS
+        .into_iter()
+        .map(|alpha| (alpha, fit(alpha)))
+        .min_by(|a, b| sum_of_squares(&a.1 .1).total_cmp(&sum_of_squares(&b.1 .1)))
+        .expect("parameter grid is not empty");
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::ExponentialSmoothing,
+        points: vec![level.max(0.0); horizon],
+        residual_sd,
+        step_sd: (0..horizon)
+            .map(|h| residual_sd * (1.0 + h as f64 * alpha * alpha).sqrt())
+            .collect(),
+    }
+}
+
+fn linear_trend(series: &[f64], horizon: usize) -> Forecast {
+    let n = series.len() as f64;
+    let mean_t = (n - 1.0) / 2.0;
+    let mean_y = series.iter().sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (t, y) in series.iter().enumerate() {
+        covariance += (t as f64 - mean_t) * (y - mean_y);
+        variance += (t as f64 - mean_t).powi(2);
+    }
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    let intercept = mean_y - slope * mean_t;
+
+    let errors: Vec<f64> = series
+        .iter()
+        .enumerate()
+        .map(|(t, y)| y - (intercept + slope * t as f64))
+        .collect();
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::LinearTrend,
+        points: (1..=horizon)
+            .map(|h| (intercept + slope * (n - 1.0 + h as f64)).max(0.0))
+            .collect(),
+        residual_sd,
+        step_sd: vec![residual_sd; horizon],
+    }
+}
+
+fn seasonal_naive(series: &[f64], season: usize, horizon: usize) -> Forecast {
+    let last_season = &series[series.len() - season..];
+    let errors: Vec<f64> = (season..series.len())
+        .map(|t| series[t] - series[t - season])
+        .collect();
+    let residual_sd = standard_deviation(&errors);
+    Forecast {
+        method: Method::SeasonalNaive,
+        points: (0..horizon).map(|h| last_season[h % season].max(0.0)).collect(),
+        residual_sd,
+        // Each further season ahead repeats the same week once more.
+        step_sd: (0..horizon)
+            .map(|h| residual_sd * ((h / season + 1) as f64).sqrt())
+            .collect(),
+    }
+}
+
+struct HoltWinters {
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    level: f64,
+    trend: f64,
+    seasonals: Vec<f64>,
+    errors: Vec<f64>,
+}
+
+// Smoothing parameters are picked by a small grid search on the in-sample
+// one-step errors; series here are a few hundred days at most.
+fn holt_winters(series: &[f64], season: usize, horizon: usize) -> Forecast {
+    let mut best: Option<(f64, HoltWinters)> = None;
+    for alpha in ALPHAS {
+        for beta in BETAS {
+            for gamma in GAMMAS {
+                let fit = fit_holt_winters(series, season, alpha, beta, gamma);
+                let sse = sum_of_squares(&fit.errors);
+                if best.as_ref().is_none_or(|(best_sse, _)| sse < *best_sse) {
+                    best = Some((sse, fit));
+                }
+            }
+        }
+    }
+    let (_, fit) = best.expect("parameter grid is not empty");
+
+    let mut damped = 0.0;
+    let points = (1..=horizon)
+        .map(|h| {
+            damped += DAMPING.powi(h as i32);
+            let seasonal = fit.seasonals[(series.len() + h - 1) % season];
+            (fit.level + damped * fit.trend + seasonal).max(0.0)
+        })
+        .collect();
+    // Variance of the h-step error of the equivalent state space model:
+    // sigma^2 * (1 + sum of c_j^2), with c_j how much an error j steps back
+    // still moves the forecast.
+    let residual_sd = standard_deviation(&fit.errors);
+    let mut accumulated: f64 = 1.0;
+    let mut damped = 0.0;
+    let step_sd = (1..=horizon)
+        .map(|h| {
+            let sd = residual_sd * accumulated.sqrt();
+            damped += DAMPING.powi(h as i32);
+            let seasonal = if h.is_multiple_of(season) { fit.gamma } else { 0.0 };
+            let c = fit.alpha * (1.0 + fit.beta * damped) + seasonal;
+            accumulated += c * c;
+            sd
+        })
+        .collect();
+    Forecast {
+        method: Method::HoltWinters,
+        points,
+        residual_sd,
+        step_sd,
+    }
+}
+
+fn fit_holt_winters(series: &[f64], season: usize, alpha: f64, beta: f64, gamma: f64) -> HoltWinters {
+    let first = &series[..season];
+    let second = &series[season..2 * season];
+    let first_mean = first.iter().sum::<f64>() / season as f64;
+    let second_mean = second.iter().sum::<f64>() / season as f64;
+
+    let mut level = first_mean;
+    let mut trend = (second_mean - first_mean) / season as f64;
+    let mut seasonals: Vec<f64> = first.iter().map(|y| y - first_mean).collect();
+    let mut errors = Vec::with_capacity(series.len() - season);
+
+    for (t, y) in series.iter().enumerate().skip(season) {
+        let s = t % season;
+        let predicted = level + DAMPING * trend + seasonals[s];
+        errors.push(y - predicted);
+
+        let previous_level = level;
+        level = alpha * (y - seasonals[s]) + (1.0 - alpha) * (level + DAMPING * trend);
+        trend = beta * (level - previous_level) + (1.0 - beta) * DAMPING * trend;
+        seasonals[s] = gamma * (y - level) + (1.0 - gamma) * seasonals[s];
+    }
+
+    HoltWinters {
+        alpha,
+        beta,
+        gamma,
+        level,
+        trend,
+        seasonals,
+        errors,
+    }
+}
+
+fn sum_of_squares(errors: &[f64]) -> f64 {
+    errors.iter().map(|e| e * e).sum()
+}
+
+fn standard_deviation(errors: &[f64]) -> f64 {
+    if errors.is_empty() {
+        return 0.0;
+    }
+    (sum_of_squares(errors) / errors.len() as f64).sqrt()
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation,
+/// accurate to about 1e-9), e.g. 1.645 for 0.95.
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    let p = p.clamp(1e-9, 1.0 - 1e-9);
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weekly(weeks: usize) -> Vec<f64> {
+        let week = [10.0, 12.0, 9.0, 11.0, 20.0, 30.0, 25.0];
+        week.iter().copied().cycle().take(weeks * WEEK).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn normal_quantile_matches_known_values() {
+        assert_close(normal_quantile(0.5), 0.0, 1e-9);
+        assert_close(normal_quantile(0.95), 1.644_853_627, 1e-6);
+        assert_close(normal_quantile(0.975), 1.959_963_985, 1e-6);
+        assert_close(normal_quantile(0.01), -2.326_347_874, 1e-6);
+    }
+
+    #[test]
+    fn interval_is_symmetric_and_floored_at_zero() {
+        let forecast = Forecast {
+            method: Method::Naive,
+            points: vec![10.0, 1.0],
+            residual_sd: 1.0,
+            step_sd: vec![1.0, 2.0],
+        };
+        let interval = forecast.interval(0.95);
+        assert_close(interval[0].0, 10.0 - 1.959_964, 1e-5);
+        assert_close(interval[0].1, 10.0 + 1.959_964, 1e-5);
+        assert_eq!(interval[1].0, 0.0);
+        assert_close(interval[1].1, 1.0 + 2.0 * 1.959_964, 1e-5);
+    }
+
+    #[test]
+    fn flat_series_gives_flat_forecast() {
+        let series = vec![5.0; 4 * WEEK];
+        for method in Method::CANDIDATES {
+            let forecast = forecast(&series, WEEK, 10, Some(method));
+            assert_eq!(forecast.method, method);
+            for point in &forecast.points {
+                assert_close(*point, 5.0, 1e-9);
+            }
+            assert_close(forecast.residual_sd, 0.0, 1e-9);
+        }
+    }
+
+    #[test]
+    fn seasonal_naive_repeats_last_week() {
+        let mut series = weekly(3);
+        series[2 * WEEK + 3] = 15.0;
+        let forecast = seasonal_naive(&series, WEEK, 2 * WEEK);
+        let last_week = &series[2 * WEEK..];
+        assert_eq!(&forecast.points[..WEEK], last_week);
+        assert_eq!(&forecast.points[WEEK..], last_week);
+    }
+
+    #[test]
+    fn holt_winters_follows_a_weekly_pattern() {
+        let series = weekly(6);
+        let forecast = holt_winters(&series, WEEK, WEEK);
+        for (point, expected) in forecast.points.iter().zip(&series[..WEEK]) {
+            assert_close(*point, *expected, 1e-6);
+        }
+    }
+
+    #[test]
+    fn naive_repeats_the_last_day() {
+        let forecast = naive(&[3.0, 4.0, 8.0], 3);
+        assert_eq!(forecast.points, vec![8.0; 3]);
+        assert!(forecast.step_sd[2] > forecast.step_sd[0]);
+    }
+
+    #[test]
+    fn short_series_fall_back_to_methods_they_can_fit() {
+        let series = weekly(1);
+        assert_eq!(forecast(&series, WEEK, 3, None).method, Method::SeasonalNaive);
+        assert_eq!(
+            forecast(&series, WEEK, 3, Some(Method::HoltWinters)).method,
+            Method::SeasonalNaive
+        );
+        assert_eq!(forecast(&series[..3], WEEK, 3, None).method, Method::Mean);
+        assert_eq!(forecast(&series[..3], WEEK, 3, Some(Method::Naive)).method, Method::Naive);
+        let empty = forecast(&[], WEEK, 3, None);
+        assert_eq!(empty.method, Method::Mean);
+        assert_eq!(empty.points, vec![0.0; 3]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Count {
+    /// Sum of transaction weights, e.g. after time decay.
+    pub weighted: f64,
+    /// Plain number of transactions.
+    pub raw: usize,
+}
+
+impl Count {
+    fn add(&mut self, weight: f64) {
+        self.weighted += weight;
+        self.raw += 1;
+    }
+
+    fn remove(&mut self, weight: f64) {
+        self.weighted = (self.weighted - weight).max(0.0);
+        self.raw = self.raw.saturating_sub(1);
+    }
+}
+
+/// Single-item rule "antecedent -> consequent" read off the pair counts.
+#[derive(Debug, Clone)]
+pub struct PairRule<T> {
+    pub antecedent: T,
+    pub consequent: T,
+    pub confidence: f64,
+    pub lift: f64,
+    pub together: usize,
+    pub antecedent_count: usize,
+    pub total: usize,
+}
+
+/// Item and pair supports that can be updated one transaction at a time, so
+/// new receipts are folded in without re-mining everything. Only pairs are
+/// kept, which is all the single-antecedent, single-consequent rules need.
+#[derive(Debug, Clone)]
+pub struct SupportCounter<T> {
+    transactions: Count,
+    items: HashMap<T, Count>,
+    pairs: HashMap<(T, T), Count>,
+}
+
+impl<T> Default for SupportCounter<T> {
+    fn default() -> Self {
+        SupportCounter {
+            transactions: Count::default(),
+            items: HashMap::new(),
+            pairs: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone + Ord> SupportCounter<T> {
+    pub fn add(&mut self, transaction: &[T], weight: f64) {
+        self.transactions.add(weight);
+        let items = distinct(transaction);
+        for (i, a) in items.iter().enumerate() {
+            self.items.entry(a.clone()).or_default().add(weight);
+            for b in &items[i + 1..] {
+                self.pairs.entry((a.clone(), b.clone())).or_default().add(weight);
+            }
+        }
+    }
+
+    /// Takes back a transaction added earlier with the same (current) weight,
+    /// e.g. when more lines of an already counted receipt arrive.
+    pub fn remove(&mut self, transaction: &[T], weight: f64) {
+        self.transactions.remove(weight);
+        let items = distinct(transaction);
+        for (i, a) in items.iter().enumerate() {
+            if let Some(count) = self.items.get_mut(a) {
+                count.remove(weight);
+                if count.raw == 0 {
+                    self.items.remove(a);
+                }
+            }
+            for b in &items[i + 1..] {
+                let key = (a.clone(), b.clone());
+                if let Some(count) = self.pairs.get_mut(&key) {
+                    count.remove(weight);
+                    if count.raw == 0 {
+                        self.pairs.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Scales every weighted count, e.g. to age them when newer transactions arrive.
+    pub fn decay(&mut self, factor: f64) {
+        self.transactions.weighted *= factor;
+        for count in self.items.values_mut().chain(self.pairs.values_mut()) {
+            count.weighted *= factor;
+        }
+    }
+
+    pub fn transactions(&self) -> Count {
+        self.transactions
+    }
+
+    pub fn item_counts(&self) -> impl Iterator<Item = (&T, &Count)> {
+        self.items.iter()
+    }
+
+    pub fn rules(&self, min_support: f64, min_confidence: f64) -> Vec<PairRule<T>> {
+        let total = self.transactions.weighted;
+        if total <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut rules = Vec::new();
+        for ((a, b), pair) in &self.pairs {
+            if pair.weighted / total < min_support {
+                continue;
+            }
+            for (antecedent, consequent) in [(a, b), (b, a)] {
+                let (Some(lhs), Some(rhs)) = (self.items.get(antecedent), self.items.get(consequent))
+                else {
+                    continue;
+                };
+                if lhs.weighted <= 0.0 || rhs.weighted <= 0.0 {
+                    continue;
+                }
+                let confidence = pair.weighted / lhs.weighted;
+                if confidence < min_confidence {
+                    continue;
+                }
+                rules.push(PairRule {
+                    antecedent: antecedent.clone(),
+                    consequent: consequent.clone(),
+                    confidence,
+                    lift: confidence / (rhs.weighted / total),
+                    together: pair.raw,
+                    antecedent_count: lhs.raw,
+                    total: self.transactions.raw,
+                });
+            }
+        }
+        rules
+    }
+}
+
+fn distinct<T: Eq + Hash + Clone + Ord>(transaction: &[T]) -> Vec<T> {
+    let mut items: Vec<T> = transaction
+        .iter()
+        .collect::<HashSet<&T>>()
+        .into_iter()
+        .cloned()
+        .collect();
+    items.sort();
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_counts(a: &SupportCounter<i32>, b: &SupportCounter<i32>) {
+        fn close(x: Count, y: Count) -> bool {
+            x.raw == y.raw && (x.weighted - y.weighted).abs() < 1e-9
+        }
+        assert!(close(a.transactions, b.transactions));
+        assert_eq!(a.items.len(), b.items.len());
+        for (item, count) in &a.items {
+            assert!(close(*count, b.items[item]), "item {item}");
+        }
+        assert_eq!(a.pairs.len(), b.pairs.len());
+        for (pair, count) in &a.pairs {
+            assert!(close(*count, b.pairs[pair]), "pair {pair:?}");
+        }
+    }
+
+    #[test]
+    fn decay_then_replacing_a_receipt_matches_a_full_recount() {
+        let mut incremental = SupportCounter::default();
+        incremental.add(&[1, 2], 1.0);
+        incremental.add(&[1, 3], 1.0);
+        incremental.add(&[2, 3], 0.5);
+        incremental.decay(0.5);
+        // More lines arrive on the second receipt.
+        incremental.remove(&[1, 3], 0.5);
+        incremental.add(&[1, 3, 2], 0.5);
+
+        let mut recount = SupportCounter::default();
+        recount.add(&[1, 2], 0.5);
+        recount.add(&[1, 2, 3], 0.5);
+        recount.add(&[2, 3], 0.25);
+
+        assert_same_counts(&incremental, &recount);
+    }
+
+    #[test]
+    fn removing_the_only_receipt_with_an_item_drops_it() {
+        let mut counter = SupportCounter::default();
+        counter.add(&[1, 2], 1.0);
+        counter.add(&[1, 4, 4], 1.0);
+        counter.remove(&[1, 4, 4], 1.0);
+
+        let mut recount = SupportCounter::default();
+        recount.add(&[1, 2], 1.0);
+        assert_same_counts(&counter, &recount);
+    }
+
+    #[test]
+    fn rules_read_confidence_and_lift_off_weighted_counts() {
+        let mut counter = SupportCounter::default();
+        counter.add(&[1, 2], 1.0);
+        counter.add(&[1, 2], 1.0);
+        counter.add(&[1, 3], 2.0);
+        counter.add(&[2], 4.0);
+
+        let rules = counter.rules(0.0, 0.0);
+        let rule = rules
+            .iter()
+            .find(|r| r.antecedent == 1 && r.consequent == 2)
+            .unwrap();
+        // 2 of item 1's weight 4 also had item 2; item 2 is in 6 of 8.
+        assert!((rule.confidence - 0.5).abs() < 1e-9);
+        assert!((rule.lift - 0.5 / 0.75).abs() < 1e-9);
+        assert_eq!((rule.together, rule.antecedent_count, rule.total), (2, 3, 4));
+
+        // Pair {1, 3} has support 2/8 and is dropped above that.
+        assert!(counter.rules(0.3, 0.0).iter().all(|r| r.consequent != 3));
+    }
+}
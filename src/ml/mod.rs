@@ -1 +1,10 @@
 pub mod apriori;
+pub mod backtest;
+pub mod collaborative;
+pub mod content;
+pub mod evaluation;
+pub mod forecasting;
+pub mod incremental;
+pub mod prefixspan;
+pub mod regressors;
+pub mod substitutes;
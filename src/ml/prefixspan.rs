@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A sequence of items each bought on a later visit than the one before it.
+#[derive(Debug, Clone)]
+pub struct SequentialPattern<T> {
+    pub items: Vec<T>,
+    /// Number of sequences (customers) containing the pattern.
+    pub support: usize,
+}
+
+/// "Customers who bought `prefix`, in that order, later came back for `next`."
+#[derive(Debug, Clone)]
+pub struct SequentialRule<T> {
+    pub prefix: Vec<T>,
+    pub next: T,
+    pub support: usize,
+    pub confidence: f64,
+}
+
+// A projected sequence: which sequence, and the first visit still available.
+type Projection = Vec<(usize, usize)>;
+
+/// PrefixSpan over sequences of visits, each visit an itemset. Patterns only
+/// grow by items from strictly later visits, so "X then Y" means Y was bought
+/// on a return visit, never on the same receipt as X.
+pub fn prefixspan<T: Eq + Hash + Clone + Ord>(
+    sequences: &[Vec<Vec<T>>],
+    min_support: usize,
+    max_length: usize,
+) -> Vec<SequentialPattern<T>> {
+    let mut patterns = Vec::new();
+    let projection: Projection = (0..sequences.len()).map(|i| (i, 0)).collect();
+    grow(sequences, &mut Vec::new(), &projection, min_support.max(1), max_length, &mut patterns);
+    patterns
+}
+
+fn grow<T: Eq + Hash + Clone + Ord>(
+    sequences: &[Vec<Vec<T>>],
+    prefix: &mut Vec<T>,
+    projection: &Projection,
+    min_support: usize,
+    max_length: usize,
+    patterns: &mut Vec<SequentialPattern<T>>,
+) {
+    if prefix.len() >= max_length {
+        return;
+    }
+
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    for (sequence, start) in projection {
+        let items: HashSet<&T> = sequences[*sequence][*start..].iter().flatten().collect();
+        for item in items {
+            *counts.entry(item).or_insert(0) += 1;
+        }
+    }
+
+    let mut frequent: Vec<(&T, usize)> = counts
+        .into_iter()
+        .filter(|(_, support)| *support >= min_support)
+        .collect();
+    frequent.sort();
+
+    for (item, support) in frequent {
+        let next_projection: Projection = projection
+            .iter()
+            .filter_map(|(sequence, start)| {
+                let visits = &sequences[*sequence];
+                let found = (*start..visits.len()).find(|v| visits[*v].contains(item))?;
+                Some((*sequence, found + 1))
+            })
+            .collect();
+
+        prefix.push(item.clone());
+        patterns.push(SequentialPattern {
+            items: prefix.clone(),
+            support,
+        });
+        grow(sequences, prefix, &next_projection, min_support, max_length, patterns);
+        prefix.pop();
+    }
+}
+
+/// Splits every pattern of two or more items into "prefix, then next item",
+/// with confidence relative to the customers who bought the prefix.
+pub fn sequential_rules<T: Eq + Hash + Clone>(
+    patterns: &[SequentialPattern<T>],
+    min_confidence: f64,
+) -> Vec<SequentialRule<T>> {
+    let supports: HashMap<&[T], usize> = patterns
+        .iter()
+        .map(|p| (p.items.as_slice(), p.support))
+        .collect();
+
+    patterns
+        .iter()
+        .filter(|p| p.items.len() > 1)
+        .filter_map(|p| {
+            let (next, prefix) = p.items.split_last()?;
+            let prefix_support = *supports.get(prefix)?;
+            let confidence = p.support as f64 / prefix_support as f64;
+            (confidence >= min_confidence).then(|| SequentialRule {
+                prefix: prefix.to_vec(),
+                next: next.clone(),
+                support: p.support,
+                confidence,
+            })
+        })
+        .collect()
+}
+
+/// "Customers who bought X on their first visit came back for Y": rules whose
+/// prefix is a single item from the first visit only.
+pub fn first_visit_rules<T: Eq + Hash + Clone + Ord>(
+    sequences: &[Vec<Vec<T>>],
+    min_support: usize,
+    min_confidence: f64,
+) -> Vec<SequentialRule<T>> {
+    let mut first: HashMap<&T, usize> = HashMap::new();
+    let mut followed: HashMap<(&T, &T), usize> = HashMap::new();
+    for visits in sequences {
+        let Some((first_visit, later)) = visits.split_first() else {
+            continue;
+        };
+        let opened: HashSet<&T> = first_visit.iter().collect();
+        let returned: HashSet<&T> = later.iter().flatten().collect();
+        for x in &opened {
+            *first.entry(x).or_insert(0) += 1;
+            for y in &returned {
+                *followed.entry((x, y)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut rules: Vec<SequentialRule<T>> = followed
+        .into_iter()
+        .filter(|(_, support)| *support >= min_support.max(1))
+        .filter_map(|((x, y), support)| {
+            let confidence = support as f64 / first[x] as f64;
+            (confidence >= min_confidence).then(|| SequentialRule {
+                prefix: vec![x.clone()],
+                next: y.clone(),
+                support,
+                confidence,
+            })
+        })
+        .collect();
+    rules.sort_by(|a, b| a.prefix.cmp(&b.prefix).then_with(|| a.next.cmp(&b.next)));
+    rules
+}
+
+/// True when `pattern` occurs in `visits` with each item on a later visit
+/// than the one before.
+pub fn contains_pattern<T: Eq>(visits: &[Vec<T>], pattern: &[T]) -> bool {
+    let mut visit = 0;
+    for item in pattern {
+        match (visit..visits.len()).find(|v| visits[*v].contains(item)) {
+            Some(found) => visit = found + 1,
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four customers' visits, oldest first.
+    fn sequences() -> Vec<Vec<Vec<i32>>> {
+        vec![
+            vec![vec![1, 2], vec![3]],
+            vec![vec![1], vec![2]],
+            vec![vec![1, 2]],
+            vec![vec![2], vec![1, 3]],
+        ]
+    }
+
+    fn support(patterns: &[SequentialPattern<i32>], items: &[i32]) -> Option<usize> {
+        patterns.iter().find(|p| p.items == items).map(|p| p.support)
+    }
+
+    #[test]
+    fn patterns_only_grow_across_visits() {
+        let patterns = prefixspan(&sequences(), 1, 2);
+
+        assert_eq!(support(&patterns, &[1]), Some(4));
+        assert_eq!(support(&patterns, &[3]), Some(2));
+        // 1 and 2 share a receipt for two customers; only one bought 2 later.
+        assert_eq!(support(&patterns, &[1, 2]), Some(1));
+        assert_eq!(support(&patterns, &[2, 1]), Some(1));
+        // The last customer bought 1 and 3 together, so only the first counts.
+        assert_eq!(support(&patterns, &[1, 3]), Some(1));
+        assert_eq!(support(&patterns, &[2, 3]), Some(2));
+        assert!(patterns.iter().all(|p| p.items.len() <= 2));
+    }
+
+    #[test]
+    fn min_support_prunes_patterns_and_rules_get_confidence() {
+        let patterns = prefixspan(&sequences(), 2, 3);
+        let mut items: Vec<_> = patterns.iter().map(|p| p.items.clone()).collect();
+        items.sort();
+        assert_eq!(items, vec![vec![1], vec![2], vec![2, 3], vec![3]]);
+
+        let rules = sequential_rules(&patterns, 0.0);
+        assert_eq!(rules.len(), 1);
+        assert_eq!((rules[0].prefix.as_slice(), rules[0].next), (&[2][..], 3));
+        assert_eq!(rules[0].support, 2);
+        assert!((rules[0].confidence - 0.5).abs() < 1e-9);
+        assert!(sequential_rules(&patterns, 0.6).is_empty());
+    }
+
+    #[test]
+    fn first_visit_rules_count_return_visits_only() {
+        let rules = first_visit_rules(&sequences(), 1, 0.0);
+        let found: Vec<_> = rules
+            .iter()
+            .map(|r| (r.prefix[0], r.next, r.support))
+            .collect();
+        assert_eq!(found, vec![(1, 2, 1), (1, 3, 1), (2, 1, 1), (2, 3, 2)]);
+        // Three customers opened with 2; two of them came back for 3.
+        assert!((rules[3].confidence - 2.0 / 3.0).abs() < 1e-9);
+
+        let strict = first_visit_rules(&sequences(), 2, 0.0);
+        assert_eq!(strict.len(), 1);
+        assert!(first_visit_rules(&sequences(), 1, 0.7).is_empty());
+    }
+
+    #[test]
+    fn contains_pattern_needs_strictly_later_visits() {
+        let visits = vec![vec![1, 2], vec![3]];
+        assert!(contains_pattern(&visits, &[1, 3]));
+        assert!(contains_pattern(&visits, &[2]));
+        assert!(contains_pattern(&visits, &[]));
+        assert!(!contains_pattern(&visits, &[1, 2]));
+        assert!(!contains_pattern(&visits, &[3, 1]));
+        assert!(contains_pattern(&[vec![1], vec![1]], &[1, 1]));
+        assert!(!contains_pattern(&[vec![1]], &[1, 1]));
+    }
+}
@@ -0,0 +1,157 @@
+use super::forecasting::{self, Forecast, Method};
+use serde::Serialize;
+
+// Same weekdays either side of a calendar day averaged into its baseline.
+const BASELINE_WEEKS: usize = 4;
+// Pseudo-days with no uplift added to every estimate, so an effect seen on
+// only a day or two is pulled towards none.
+const PRIOR_DAYS: f64 = 2.0;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+
+/// Kinds of calendar day whose effect on demand is estimated separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Regressor {
+    PublicHoliday,
+    Payday,
+    Event,
+}
+
+impl Regressor {
+    pub const ALL: [Regressor; 3] = [Regressor::PublicHoliday, Regressor::Payday, Regressor::Event];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Regressor::PublicHoliday => "public_holiday",
+            Regressor::Payday => "payday",
+            Regressor::Event => "event",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Regressor::ALL
+            .into_iter()
+            .find(|regressor| regressor.as_str() == value.trim())
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The regressors that apply to one day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DayFeatures(u8);
+
+impl DayFeatures {
+    pub fn insert(&mut self, regressor: Regressor) {
+        self.0 |= regressor.bit();
+    }
+
+    pub fn contains(&self, regressor: Regressor) -> bool {
+        self.0 & regressor.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Estimated multiplicative effect of one regressor, e.g. 1.3 for 30% more
+/// units than an ordinary day of the same weekday.
+#[derive(Debug, Clone, Serialize)]
+pub struct Uplift {
+    pub regressor: Regressor,
+    pub factor: f64,
+    /// Days in the history the estimate is based on.
+    pub days: usize,
+}
+
+/// Demand expected on day `t` had it been an ordinary day: the average of the
+/// same weekday in the weeks around it that had no regressors. `None` when
+/// there is no such day.
+pub fn baseline(series: &[f64], features: &[DayFeatures], t: usize, season: usize) -> Option<f64> {
+    let season = season.max(1);
+    let values: Vec<f64> = (1..=BASELINE_WEEKS)
+        .flat_map(|k| [t.checked_sub(k * season), Some(t + k * season)])
+        .flatten()
+        .filter(|d| *d < series.len() && features_at(features, *d).is_empty())
+        .map(|d| series[d])
+        .collect();
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Uplift of every regressor that occurs in the history, as the ratio of
+/// units sold to baseline units over its days. Days with several regressors
+/// count towards each of them.
+pub fn fit_uplifts(series: &[f64], features: &[DayFeatures], season: usize) -> Vec<Uplift> {
+    Regressor::ALL
+        .iter()
+        .filter_map(|regressor| {
+            let mut actual = 0.0;
+            let mut expected = 0.0;
+            let mut days = 0;
+            for t in 0..series.len() {
+                if !features_at(features, t).contains(*regressor) {
+                    continue;
+                }
+                if let Some(base) = baseline(series, features, t, season) {
+                    actual += series[t];
+                    expected += base;
+                    days += 1;
+                }
+            }
+            if days == 0 {
+                return None;
+            }
+            let prior = PRIOR_DAYS * expected / days as f64;
+            (expected + prior > 0.0).then(|| Uplift {
+                regressor: *regressor,
+                factor: ((actual + prior) / (expected + prior)).clamp(MIN_FACTOR, MAX_FACTOR),
+                days,
+            })
+        })
+        .collect()
+}
+
+/// Forecasts like [`forecasting::forecast`], with calendar effects taken out
+/// of the history before fitting and put back into the forecast. `features`
+/// covers the history followed by the horizon; days beyond it are ordinary.
+pub fn forecast(
+    series: &[f64],
+    features: &[DayFeatures],
+    season: usize,
+    horizon: usize,
+    method: Option<Method>,
+) -> (Forecast, Vec<Uplift>) {
+    let uplifts = fit_uplifts(series, features, season);
+    if uplifts.is_empty() {
+        return (forecasting::forecast(series, season, horizon, method), uplifts);
+    }
+
+    let adjusted: Vec<f64> = series
+        .iter()
+        .enumerate()
+        .map(|(t, units)| units / factor(&uplifts, features_at(features, t)))
+        .collect();
+    let mut forecast = forecasting::forecast(&adjusted, season, horizon, method);
+    for h in 0..horizon {
+        let factor = factor(&uplifts, features_at(features, series.len() + h));
+        forecast.points[h] *= factor;
+        forecast.step_sd[h] *= factor;
+    }
+    (forecast, uplifts)
+}
+
+fn factor(uplifts: &[Uplift], day: DayFeatures) -> f64 {
+    uplifts
+        .iter()
+        .filter(|uplift| day.contains(uplift.regressor))
+        .map(|uplift| uplift.factor)
+        .product()
+}
+
+fn features_at(features: &[DayFeatures], t: usize) -> DayFeatures {
+    features.get(t).copied().unwrap_or_default()
+}
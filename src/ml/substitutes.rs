@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+#[derive(Debug, Clone)]
+pub struct PairLift {
+    /// Transactions containing the other item.
+    pub support: f64,
+    pub together: f64,
+    /// Co-occurrences expected if the two items were bought independently.
+    pub expected: f64,
+    pub lift: f64,
+}
+
+/// Lift of `target` against every other item in the transactions. A lift well
+/// below 1 means the pair is bought together less often than chance, the
+/// signature of items customers choose between rather than combine.
+pub fn lifts_against<T: Eq + Hash + Clone>(
+    transactions: &[Vec<T>],
+    target: &T,
+) -> HashMap<T, PairLift> {
+    let mut support: HashMap<T, f64> = HashMap::new();
+    let mut together: HashMap<T, f64> = HashMap::new();
+    let mut target_support = 0.0;
+
+    for transaction in transactions {
+        let items: HashSet<&T> = transaction.iter().collect();
+        let has_target = items.contains(target);
+        if has_target {
+            target_support += 1.0;
+        }
+        for item in items {
+            if item == target {
+                continue;
+            }
+            *support.entry(item.clone()).or_insert(0.0) += 1.0;
+            if has_target {
+                *together.entry(item.clone()).or_insert(0.0) += 1.0;
+            }
+        }
+    }
+
+    let total = transactions.len().max(1) as f64;
+    support
+        .into_iter()
+        .map(|(item, item_support)| {
+            let observed = together.get(&item).copied().unwrap_or(0.0);
+            let expected = target_support * item_support / total;
+            let lift = if expected > 0.0 { observed / expected } else { 1.0 };
+            let pair = PairLift {
+                support: item_support,
+                together: observed,
+                expected,
+                lift,
+            };
+            (item, pair)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lift_compares_co_occurrence_with_chance() {
+        let transactions = vec![vec![1, 2], vec![1, 2], vec![1, 3], vec![2], vec![3], vec![3, 3]];
+        let lifts = lifts_against(&transactions, &1);
+        assert_eq!(lifts.len(), 2);
+
+        // Both are on half the receipts, like the target, so 1.5 are expected together.
+        let complement = &lifts[&2];
+        assert_eq!((complement.support, complement.together), (3.0, 2.0));
+        assert!((complement.expected - 1.5).abs() < 1e-9);
+        assert!((complement.lift - 4.0 / 3.0).abs() < 1e-9);
+
+        let substitute = &lifts[&3];
+        assert_eq!((substitute.support, substitute.together), (3.0, 1.0));
+        assert!((substitute.lift - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lift_is_neutral_without_the_target() {
+        let lifts = lifts_against(&[vec![2], vec![3]], &1);
+        assert!(lifts.values().all(|pair| pair.expected == 0.0 && pair.lift == 1.0));
+        assert!(lifts_against::<i32>(&[], &1).is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::ml::prefixspan::{self, SequentialRule};
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::info;
+
+// Loosest thresholds patterns are mined at.
+const MIN_SUPPORT: usize = 5;
+const MIN_CONFIDENCE: f64 = 0.1;
+const MAX_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SequenceOptions {
+    /// Minimum number of customers that must follow a pattern.
+    pub min_support: Option<usize>,
+    pub min_confidence: Option<f64>,
+    /// Longest pattern mined, counting the next-visit item.
+    pub max_length: Option<usize>,
+    /// Only "bought X on the first visit, came back for Y" patterns.
+    pub first_visit: Option<bool>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternItem {
+    pub product_id: i32,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VisitPattern {
+    pub first_visit: bool,
+    pub prefix: Vec<PatternItem>,
+    pub next: PatternItem,
+    pub customers: usize,
+    pub confidence: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NextVisitSuggestion {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub confidence: f64,
+    pub customers: usize,
+    /// Products from the customer's earlier visits that led to the suggestion.
+    pub because_of: Vec<PatternItem>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct VisitRow {
+    customer_id: i32,
+    visit_date: NaiveDate,
+    product_id: i32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ProductName {
+    product_id: i32,
+    name: Option<String>,
+}
+
+/// Visit patterns mined from every customer's history at the loosest
+/// thresholds served; requests narrow them down with stricter options.
+#[derive(Debug, Clone, Default)]
+pub struct VisitPatterns {
+    first_visit: Vec<SequentialRule<i32>>,
+    sequential: Vec<SequentialRule<i32>>,
+}
+
+impl VisitPatterns {
+    fn first_visit_rules<'a>(&'a self, options: &'a SequenceOptions) -> impl Iterator<Item = &'a SequentialRule<i32>> {
+        self.first_visit.iter().filter(move |rule| accepts(rule, options))
+    }
+
+    fn sequential_rules<'a>(&'a self, options: &'a SequenceOptions) -> impl Iterator<Item = &'a SequentialRule<i32>> {
+        let first_visit_only = options.first_visit.unwrap_or(false);
+        self.sequential
+            .iter()
+            .filter(move |rule| !first_visit_only && accepts(rule, options))
+    }
+}
+
+pub async fn mine_visit_patterns(pool: &PgPool) -> Result<VisitPatterns> {
+    let sequences: Vec<Vec<Vec<i32>>> = get_visit_sequences(pool, None).await?.into_values().collect();
+    let patterns = mine(&sequences);
+    info!(
+        "Mined {} first-visit and {} sequential visit patterns.",
+        patterns.first_visit.len(),
+        patterns.sequential.len()
+    );
+    Ok(patterns)
+}
+
+pub async fn get_sequential_patterns(
+    pool: &PgPool,
+    patterns: &VisitPatterns,
+    options: &SequenceOptions,
+) -> Result<Vec<VisitPattern>> {
+    let mut rules: Vec<(bool, &SequentialRule<i32>)> = patterns
+        .first_visit_rules(options)
+        .map(|rule| (true, rule))
+        .chain(patterns.sequential_rules(options).map(|rule| (false, rule)))
+        .collect();
+    rules.sort_by(|a, b| {
+        b.1.confidence
+            .partial_cmp(&a.1.confidence)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.1.support.cmp(&a.1.support))
+    });
+    rules.truncate(options.limit.unwrap_or(50));
+
+    let product_ids = rules.iter().flat_map(|(_, r)| r.prefix.iter().chain([&r.next]));
+    let names = get_product_names(pool, product_ids).await?;
+    Ok(rules
+        .into_iter()
+        .map(|(first_visit, rule)| VisitPattern {
+            first_visit,
+            prefix: rule.prefix.iter().map(|id| item(*id, &names)).collect(),
+            next: item(rule.next, &names),
+            customers: rule.support,
+            confidence: rule.confidence,
+        })
+        .collect())
+}
+
+/// What the customer is likely to come back for next, from the patterns their
+/// own visits match. Products they have already bought are left out, since a
+/// loyalty campaign is about getting them to try something new.
+pub async fn get_next_visit_suggestions(
+    pool: &PgPool,
+    patterns: &VisitPatterns,
+    customer_id: i32,
+    options: &SequenceOptions,
+) -> Result<Vec<NextVisitSuggestion>> {
+    let mut sequences = get_visit_sequences(pool, Some(customer_id)).await?;
+    let Some(visits) = sequences.remove(&customer_id) else {
+        return Ok(Vec::new());
+    };
+
+    let bought: Vec<i32> = visits.iter().flatten().copied().collect();
+    let first_visit = visits.first().cloned().unwrap_or_default();
+    let matching = patterns
+        .first_visit_rules(options)
+        .filter(|rule| first_visit.contains(&rule.prefix[0]))
+        .chain(
+            patterns
+                .sequential_rules(options)
+                .filter(|rule| prefixspan::contains_pattern(&visits, &rule.prefix)),
+        )
+        .filter(|rule| !bought.contains(&rule.next));
+
+    let mut best: HashMap<i32, &SequentialRule<i32>> = HashMap::new();
+    for rule in matching {
+        let entry = best.entry(rule.next).or_insert(rule);
+        if rule.confidence > entry.confidence {
+            *entry = rule;
+        }
+    }
+    let mut rules: Vec<&SequentialRule<i32>> = best.into_values().collect();
+    rules.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal));
+    rules.truncate(options.limit.unwrap_or(10));
+
+    let product_ids = rules.iter().flat_map(|r| r.prefix.iter().chain([&r.next]));
+    let names = get_product_names(pool, product_ids).await?;
+    Ok(rules
+        .into_iter()
+        .map(|rule| NextVisitSuggestion {
+            product_id: rule.next,
+            name: names.get(&rule.next).cloned().flatten(),
+            confidence: rule.confidence,
+            customers: rule.support,
+            because_of: rule.prefix.iter().map(|id| item(*id, &names)).collect(),
+        })
+        .collect())
+}
+
+fn mine(sequences: &[Vec<Vec<i32>>]) -> VisitPatterns {
+    // Customers with a single visit can only ever support one-item patterns.
+    let returning: Vec<Vec<Vec<i32>>> = sequences.iter().filter(|v| v.len() > 1).cloned().collect();
+    let first_visit = prefixspan::first_visit_rules(&returning, MIN_SUPPORT, MIN_CONFIDENCE);
+    let patterns = prefixspan::prefixspan(&returning, MIN_SUPPORT, MAX_LENGTH);
+    VisitPatterns {
+        first_visit,
+        sequential: prefixspan::sequential_rules(&patterns, MIN_CONFIDENCE),
+    }
+}
+
+// Options looser than the mined thresholds get what was mined.
+fn accepts(rule: &SequentialRule<i32>, options: &SequenceOptions) -> bool {
+    rule.support >= options.min_support.unwrap_or(MIN_SUPPORT)
+        && rule.confidence >= options.min_confidence.unwrap_or(MIN_CONFIDENCE)
+        && rule.prefix.len() < options.max_length.unwrap_or(MAX_LENGTH).max(2)
+}
+
+// One visit per customer per day, so a meal split over two receipts is not
+// mistaken for a return visit.
+async fn get_visit_sequences(
+    pool: &PgPool,
+    customer_id: Option<i32>,
+) -> Result<HashMap<i32, Vec<Vec<i32>>>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, VisitRow>(
+        r#"
+        SELECT r.customer_id, r.transaction_date::date AS visit_date, s.product_id
+        FROM sales s
+        JOIN receipts r ON s.receipt_id = r.receipt_id
+        WHERE r.customer_id IS NOT NULL
+          AND r.transaction_date IS NOT NULL
+          AND s.product_id IS NOT NULL
+          AND ($1::INTEGER IS NULL OR r.customer_id = $1)
+        ORDER BY r.customer_id, visit_date
+        "#,
+    )
+    .bind(customer_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut sequences: HashMap<i32, Vec<(NaiveDate, Vec<i32>)>> = HashMap::new();
+    for row in rows {
+        let visits = sequences.entry(row.customer_id).or_default();
+        match visits.last_mut() {
+            Some((date, items)) if *date == row.visit_date => items.push(row.product_id),
+            _ => visits.push((row.visit_date, vec![row.product_id])),
+        }
+    }
+
+    Ok(sequences
+        .into_iter()
+        .map(|(customer_id, visits)| {
+            let visits = visits
+                .into_iter()
+                .map(|(_, mut items)| {
+                    items.sort();
+                    items.dedup();
+                    items
+                })
+                .collect();
+            (customer_id, visits)
+        })
+        .collect())
+}
+
+async fn get_product_names(
+    pool: &PgPool,
+    product_ids: impl Iterator<Item = &i32>,
+) -> Result<HashMap<i32, Option<String>>, sqlx::Error> {
+    let mut product_ids: Vec<i32> = product_ids.copied().collect();
+    product_ids.sort();
+    product_ids.dedup();
+    let rows = sqlx::query_as::<_, ProductName>(
+        "SELECT product_id, name FROM products WHERE product_id = ANY($1)",
+    )
+    .bind(&product_ids)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| (row.product_id, row.name)).collect())
+}
+
+fn item(product_id: i32, names: &HashMap<i32, Option<String>>) -> PatternItem {
+    PatternItem {
+        product_id,
+        name: names.get(&product_id).cloned().flatten(),
+    }
+}
@@ -0,0 +1,415 @@
+use crate::calendar;
+use crate::errors::RequestError;
+use crate::forecast_accuracy;
+use crate::forecasting::{self, DemandForecast, ForecastOptions};
+use anyhow::{bail, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+
+// Recipes may use other recipes (a sauce, a dough); deeper nesting than this
+// is treated as a cycle.
+const MAX_RECIPE_DEPTH: usize = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct NewIngredient {
+    pub ingredient_product_id: i32,
+    /// Per batch, in the ingredient's own unit.
+    pub quantity: f64,
+    pub yield_ratio: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewRecipe {
+    pub dish_product_id: i32,
+    /// Portions one batch makes.
+    pub yield_portions: Option<f64>,
+    pub notes: Option<String>,
+    pub ingredients: Vec<NewIngredient>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RecipeIngredient {
+    #[serde(skip)]
+    pub dish_product_id: i32,
+    pub ingredient_product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub inventory_type: Option<String>,
+    pub quantity: f32,
+    pub yield_ratio: f32,
+    /// Quantity bought per portion of the dish, after the yield losses.
+    #[sqlx(skip)]
+    pub quantity_per_portion: f64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Recipe {
+    pub dish_product_id: i32,
+    pub dish_name: Option<String>,
+    pub yield_portions: f32,
+    pub notes: Option<String>,
+    pub updated_at: NaiveDateTime,
+    #[sqlx(skip)]
+    pub ingredients: Vec<RecipeIngredient>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequirementOptions {
+    pub horizon: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DishUsage {
+    pub dish_product_id: i32,
+    pub name: Option<String>,
+    pub forecast_portions: f64,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyRequirement {
+    pub date: NaiveDate,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngredientRequirement {
+    pub ingredient_product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub current_stock: Option<f32>,
+    pub total_quantity: f64,
+    /// Requirement beyond current stock over the horizon.
+    pub shortfall: f64,
+    pub daily: Vec<DailyRequirement>,
+    pub dishes: Vec<DishUsage>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct BomRow {
+    dish_product_id: i32,
+    ingredient_product_id: i32,
+    quantity: f32,
+    yield_ratio: f32,
+    yield_portions: f32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct IngredientRow {
+    product_id: i32,
+    name: Option<String>,
+    unit: Option<String>,
+    current_stock: Option<f32>,
+}
+
+/// Creates the dish's recipe or replaces it, ingredients and all.
+pub async fn save_recipe(pool: &PgPool, new: &NewRecipe) -> Result<Recipe> {
+    let yield_portions = new.yield_portions.unwrap_or(1.0);
+    if yield_portions <= 0.0 {
+        bail!(RequestError::Invalid(
+            "A recipe must yield a positive number of portions".to_string()
+        ));
+    }
+    if new.ingredients.is_empty() {
+        bail!(RequestError::Invalid("A recipe needs at least one ingredient".to_string()));
+    }
+    let mut seen = HashSet::new();
+    for ingredient in &new.ingredients {
+        if ingredient.ingredient_product_id == new.dish_product_id {
+            bail!(RequestError::Invalid(format!(
+                "Product {} cannot be an ingredient of itself",
+                new.dish_product_id
+            )));
+        }
+        if !seen.insert(ingredient.ingredient_product_id) {
+            bail!(RequestError::Invalid(format!(
+                "Ingredient {} is listed twice",
+                ingredient.ingredient_product_id
+            )));
+        }
+        if ingredient.quantity <= 0.0 {
+            bail!(RequestError::Invalid(format!(
+                "Ingredient {} must have a positive quantity",
+                ingredient.ingredient_product_id
+            )));
+        }
+        if ingredient.yield_ratio.is_some_and(|r| r <= 0.0 || r > 1.0) {
+            bail!(RequestError::Invalid(format!(
+                "Ingredient {} must have a yield ratio in (0, 1]",
+                ingredient.ingredient_product_id
+            )));
+        }
+    }
+
+    let mut product_ids: Vec<i32> = seen.into_iter().collect();
+    product_ids.push(new.dish_product_id);
+    let known: Vec<i32> =
+        sqlx::query_scalar("SELECT product_id FROM products WHERE product_id = ANY($1)")
+            .bind(&product_ids)
+            .fetch_all(pool)
+            .await?;
+    if let Some(missing) = product_ids.iter().find(|id| !known.contains(id)) {
+        bail!(RequestError::NotFound(format!("Product {} does not exist", missing)));
+    }
+
+    let mut bom = load_bom(pool).await?;
+    bom.insert(
+        new.dish_product_id,
+        new.ingredients
+            .iter()
+            .map(|i| (i.ingredient_product_id, i.quantity))
+            .collect(),
+    );
+    if !usage_per_portion(&bom).contains_key(&new.dish_product_id) {
+        bail!(RequestError::Invalid(format!(
+            "Recipe for product {} would make recipes use each other in a cycle",
+            new.dish_product_id
+        )));
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO recipes (dish_product_id, yield_portions, notes)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (dish_product_id) DO UPDATE SET
+            yield_portions = EXCLUDED.yield_portions,
+            notes = EXCLUDED.notes,
+            updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(new.dish_product_id)
+    .bind(yield_portions as f32)
+    .bind(&new.notes)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM recipe_ingredients WHERE dish_product_id = $1")
+        .bind(new.dish_product_id)
+        .execute(&mut *tx)
+        .await?;
+    for ingredient in &new.ingredients {
+        sqlx::query(
+            "INSERT INTO recipe_ingredients (dish_product_id, ingredient_product_id, quantity, yield_ratio)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(new.dish_product_id)
+        .bind(ingredient.ingredient_product_id)
+        .bind(ingredient.quantity as f32)
+        .bind(ingredient.yield_ratio.unwrap_or(1.0) as f32)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    match get_recipe(pool, new.dish_product_id).await? {
+        Some(recipe) => Ok(recipe),
+        None => bail!("Recipe for product {} disappeared while saving", new.dish_product_id),
+    }
+}
+
+pub async fn get_recipes(pool: &PgPool) -> Result<Vec<Recipe>, sqlx::Error> {
+    load_recipes(pool, None).await
+}
+
+pub async fn get_recipe(pool: &PgPool, dish_product_id: i32) -> Result<Option<Recipe>, sqlx::Error> {
+    Ok(load_recipes(pool, Some(dish_product_id)).await?.into_iter().next())
+}
+
+/// `false` when the dish had no recipe.
+pub async fn delete_recipe(pool: &PgPool, dish_product_id: i32) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM recipes WHERE dish_product_id = $1")
+        .bind(dish_product_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn load_recipes(
+    pool: &PgPool,
+    dish_product_id: Option<i32>,
+) -> Result<Vec<Recipe>, sqlx::Error> {
+    let mut recipes = sqlx::query_as::<_, Recipe>(
+        "SELECT r.dish_product_id, p.name AS dish_name, r.yield_portions, r.notes, r.updated_at
+         FROM recipes r
+         JOIN products p ON p.product_id = r.dish_product_id
+         WHERE ($1::INTEGER IS NULL OR r.dish_product_id = $1)
+         ORDER BY r.dish_product_id",
+    )
+    .bind(dish_product_id)
+    .fetch_all(pool)
+    .await?;
+
+    let ingredients = sqlx::query_as::<_, RecipeIngredient>(
+        "SELECT ri.dish_product_id, ri.ingredient_product_id, p.name, p.unit, p.inventory_type,
+                ri.quantity, ri.yield_ratio
+         FROM recipe_ingredients ri
+         JOIN products p ON p.product_id = ri.ingredient_product_id
+         WHERE ($1::INTEGER IS NULL OR ri.dish_product_id = $1)
+         ORDER BY ri.ingredient_product_id",
+    )
+    .bind(dish_product_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_dish: HashMap<i32, Vec<RecipeIngredient>> = HashMap::new();
+    for mut ingredient in ingredients {
+        ingredient.quantity_per_portion = ingredient.quantity as f64;
+        by_dish.entry(ingredient.dish_product_id).or_default().push(ingredient);
+    }
+    for recipe in &mut recipes {
+        recipe.ingredients = by_dish.remove(&recipe.dish_product_id).unwrap_or_default();
+        for ingredient in &mut recipe.ingredients {
+            ingredient.quantity_per_portion /=
+                recipe.yield_portions as f64 * ingredient.yield_ratio as f64;
+        }
+    }
+    Ok(recipes)
+}
+
+/// Each recipe's direct ingredients, as quantity bought per portion made.
+pub async fn load_bom(pool: &PgPool) -> Result<HashMap<i32, Vec<(i32, f64)>>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, BomRow>(
+        "SELECT ri.dish_product_id, ri.ingredient_product_id, ri.quantity, ri.yield_ratio, r.yield_portions
+         FROM recipe_ingredients ri
+         JOIN recipes r ON r.dish_product_id = ri.dish_product_id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut bom: HashMap<i32, Vec<(i32, f64)>> = HashMap::new();
+    for row in rows {
+        let per_portion = row.quantity as f64 / (row.yield_portions as f64 * row.yield_ratio as f64);
+        bom.entry(row.dish_product_id)
+            .or_default()
+            .push((row.ingredient_product_id, per_portion));
+    }
+    Ok(bom)
+}
+
+/// Raw ingredients per portion of every dish, following recipes used inside
+/// other recipes down to products without one. Dishes caught in a cycle are
+/// left out.
+pub fn usage_per_portion(bom: &HashMap<i32, Vec<(i32, f64)>>) -> HashMap<i32, HashMap<i32, f64>> {
+    fn expand(
+        bom: &HashMap<i32, Vec<(i32, f64)>>,
+        product: i32,
+        factor: f64,
+        depth: usize,
+        usage: &mut HashMap<i32, f64>,
+    ) -> bool {
+        let Some(ingredients) = bom.get(&product) else {
+            *usage.entry(product).or_insert(0.0) += factor;
+            return true;
+        };
+        if depth > MAX_RECIPE_DEPTH {
+            return false;
+        }
+        ingredients
+            .iter()
+            .all(|(ingredient, per_portion)| expand(bom, *ingredient, factor * per_portion, depth + 1, usage))
+    }
+
+    bom.keys()
+        .filter_map(|dish| {
+            let mut usage = HashMap::new();
+            expand(bom, *dish, 1.0, 0, &mut usage).then_some((*dish, usage))
+        })
+        .collect()
+}
+
+/// Ingredient quantities needed to make the forecast dishes, day by day.
+pub async fn get_ingredient_requirements(
+    pool: &PgPool,
+    options: &RequirementOptions,
+) -> Result<Vec<IngredientRequirement>> {
+    let bom = load_bom(pool).await?;
+    let usage = usage_per_portion(&bom);
+    if usage.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let forecast_options = ForecastOptions {
+        horizon: Some(options.horizon.unwrap_or(7).max(1)),
+        ..Default::default()
+    };
+    let series = forecasting::get_demand_series(pool, None, None).await?;
+    let selected = forecast_accuracy::get_selected_methods(pool).await?;
+    let calendar = calendar::load_calendar(pool).await?;
+
+    let dishes: Vec<i32> = usage.keys().copied().collect();
+    let forecasts: HashMap<i32, DemandForecast> = forecasting::fit_blocking(move || {
+        dishes
+            .iter()
+            .filter_map(|dish| series.get(dish))
+            .map(|series| {
+                let forecast = forecasting::forecast_series(series, &forecast_options, &selected, &calendar);
+                (series.product_id, forecast)
+            })
+            .collect()
+    })
+    .await?;
+
+    let mut requirements: HashMap<i32, IngredientRequirement> = HashMap::new();
+    for (dish, ingredients) in &usage {
+        let Some(forecast) = forecasts.get(dish) else {
+            continue;
+        };
+        let portions: f64 = forecast.daily.iter().map(|d| d.units).sum();
+        for (ingredient, per_portion) in ingredients {
+            let requirement = requirements.entry(*ingredient).or_insert_with(|| IngredientRequirement {
+                ingredient_product_id: *ingredient,
+                name: None,
+                unit: None,
+                current_stock: None,
+                total_quantity: 0.0,
+                shortfall: 0.0,
+                daily: forecast
+                    .daily
+                    .iter()
+                    .map(|d| DailyRequirement {
+                        date: d.date,
+                        quantity: 0.0,
+                    })
+                    .collect(),
+                dishes: Vec::new(),
+            });
+            for (day, dish_day) in requirement.daily.iter_mut().zip(&forecast.daily) {
+                day.quantity += dish_day.units * per_portion;
+            }
+            requirement.total_quantity += portions * per_portion;
+            requirement.dishes.push(DishUsage {
+                dish_product_id: *dish,
+                name: forecast.name.clone(),
+                forecast_portions: portions,
+                quantity: portions * per_portion,
+            });
+        }
+    }
+
+    let ids: Vec<i32> = requirements.keys().copied().collect();
+    let products = sqlx::query_as::<_, IngredientRow>(
+        "SELECT product_id, name, unit, current_stock FROM products WHERE product_id = ANY($1)",
+    )
+    .bind(&ids)
+    .fetch_all(pool)
+    .await?;
+    for product in products {
+        if let Some(requirement) = requirements.get_mut(&product.product_id) {
+            requirement.name = product.name;
+            requirement.unit = product.unit;
+            requirement.current_stock = product.current_stock;
+            requirement.shortfall = (requirement.total_quantity
+                - product.current_stock.unwrap_or(0.0).max(0.0) as f64)
+                .max(0.0);
+        }
+    }
+
+    let mut requirements: Vec<IngredientRequirement> = requirements.into_values().collect();
+    for requirement in &mut requirements {
+        requirement
+            .dishes
+            .sort_by(|a, b| b.quantity.total_cmp(&a.quantity));
+    }
+    requirements.sort_by_key(|r| r.ingredient_product_id);
+    Ok(requirements)
+}
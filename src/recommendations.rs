@@ -1,76 +1,1365 @@
-use crate::ml::apriori;
-use serde::Serialize;
+use crate::dayparts::DaypartConfig;
+use crate::experiments::{self, ExperimentWithVariants, Variant};
+use crate::explanations::{Explanation, ExplanationSource, RuleCounts};
+use crate::feedback::{self, FeedbackStats};
+use crate::ml::incremental::SupportCounter;
+use crate::ml::{apriori, collaborative, content};
+use crate::next_visit::{self, VisitPatterns};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Recommendation {
     pub product_id: i32,
     pub score: f64,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub department: Option<String>,
+    pub selling_price: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
+}
+
+impl Recommendation {
+    fn new(product_id: i32, score: f64) -> Self {
+        Recommendation {
+            product_id,
+            score,
+            name: None,
+            category: None,
+            department: None,
+            selling_price: None,
+            explanation: None,
+        }
+    }
+
+    fn explained(mut self, explanation: Explanation) -> Self {
+        self.explanation = Some(explanation);
+        self
+    }
+}
+
+pub type RecommendationMap = HashMap<i32, Vec<Recommendation>>;
+
+// Dayparts with fewer baskets than this fall back to the overall rules.
+const MIN_SEGMENT_TRANSACTIONS: usize = 50;
+// Customers with fewer receipts than this get association rules instead.
+const MIN_CUSTOMER_RECEIPTS: i64 = 3;
+const SIMILAR_ITEMS_PER_PRODUCT: usize = 50;
+const CUSTOMER_RECOMMENDATION_LIMIT: usize = 10;
+// How many of a target category's best sellers a category-level rule expands into.
+const LEVEL_TOP_SELLERS: usize = 3;
+const CONTENT_NEIGHBOURS: usize = 10;
+
+#[derive(Debug, Clone, Default)]
+pub struct RecommendationModel {
+    pub version: Option<i32>,
+    pub overall: RecommendationMap,
+    pub dayparts: HashMap<String, RecommendationMap>,
+    pub item_similarities: HashMap<i32, Vec<(i32, f64)>>,
+    pub categories: LevelRules,
+    pub departments: LevelRules,
+    /// Rule-bearing products that look most like each rarely sold product.
+    pub content_neighbours: HashMap<i32, Vec<(i32, f64)>>,
+    pub receipt_counts: HashMap<i32, usize>,
+    pub cold_start_receipts: f64,
+    /// First and last basket the rules were mined from.
+    pub training_window: Option<(NaiveDateTime, NaiveDateTime)>,
+    pub product_values: HashMap<i32, ProductValue>,
+}
+
+impl RecommendationModel {
+    pub fn len(&self) -> usize {
+        self.overall.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overall.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProductInfo {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub department: Option<String>,
+    pub unit: Option<String>,
+    pub selling_price: Option<f32>,
+}
+
+/// Association rules mined over one level of the product hierarchy, e.g.
+/// "Grills -> Drinks", for products too rarely sold to have item-level rules.
+#[derive(Debug, Clone, Default)]
+pub struct LevelRules {
+    source: ExplanationSource,
+    product_levels: HashMap<i32, String>,
+    rules: HashMap<String, Vec<LevelRule>>,
+    // Best sellers of each level with their share of the level's sales.
+    top_sellers: HashMap<String, Vec<(i32, f64)>>,
+}
+
+#[derive(Debug, Clone)]
+struct LevelRule {
+    target: String,
+    confidence: f64,
+    lift: f64,
+    counts: RuleCounts,
+}
+
+impl LevelRules {
+    fn build(
+        source: ExplanationSource,
+        transactions: &[Vec<i32>],
+        weights: &[f64],
+        product_levels: HashMap<i32, String>,
+        config: &RecommendationConfig,
+    ) -> Self {
+        let level_transactions: Vec<Vec<String>> = transactions
+            .iter()
+            .map(|items| {
+                let mut levels: Vec<String> = items
+                    .iter()
+                    .filter_map(|id| product_levels.get(id).cloned())
+                    .collect();
+                levels.sort();
+                levels.dedup();
+                levels
+            })
+            .collect();
+
+        let level_rules: Vec<_> =
+            apriori::apriori(&level_transactions, weights, config.min_support, config.min_confidence)
+                .into_iter()
+                .filter(|rule| rule.lhs.len() == 1)
+                .collect();
+        let counts = rule_counts(&level_transactions, &level_rules);
+
+        let mut rules: HashMap<String, Vec<LevelRule>> = HashMap::new();
+        for rule in level_rules {
+            let antecedent = &rule.lhs[0];
+            let targets = rules.entry(antecedent.clone()).or_default();
+            targets.extend(rule.rhs.iter().map(|level| LevelRule {
+                target: level.clone(),
+                confidence: rule.confidence,
+                lift: rule.lift,
+                counts: counts[&(antecedent.clone(), level.clone())],
+            }));
+        }
+        for targets in rules.values_mut() {
+            targets.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal));
+        }
+
+        let mut sales: HashMap<&String, HashMap<i32, f64>> = HashMap::new();
+        for (items, weight) in transactions.iter().zip(weights) {
+            for id in items {
+                if let Some(level) = product_levels.get(id) {
+                    *sales.entry(level).or_default().entry(*id).or_insert(0.0) += weight;
+                }
+            }
+        }
+        let top_sellers = sales
+            .into_iter()
+            .map(|(level, products)| {
+                let total: f64 = products.values().sum();
+                let mut ranked: Vec<(i32, f64)> = products
+                    .into_iter()
+                    .map(|(id, sold)| (id, sold / total))
+                    .collect();
+                ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                ranked.truncate(LEVEL_TOP_SELLERS);
+                (level.clone(), ranked)
+            })
+            .collect();
+
+        LevelRules {
+            source,
+            product_levels,
+            rules,
+            top_sellers,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Resolves the rules of the product's level to the best sellers of each
+    /// target level, scored as the rule confidence times the seller's share.
+    fn recommend(&self, product_id: i32) -> Vec<Recommendation> {
+        let Some((level, targets)) = self
+            .product_levels
+            .get(&product_id)
+            .and_then(|level| Some((level, self.rules.get(level)?)))
+        else {
+            return Vec::new();
+        };
+
+        let mut recommendations: Vec<Recommendation> = targets
+            .iter()
+            .flat_map(|rule| {
+                self.top_sellers
+                    .get(&rule.target)
+                    .into_iter()
+                    .flatten()
+                    .map(move |(id, share)| {
+                        Recommendation::new(*id, rule.confidence * share).explained(Explanation::level(
+                            self.source,
+                            level,
+                            &rule.target,
+                            rule.counts,
+                            rule.confidence,
+                            rule.lift,
+                            *share,
+                        ))
+                    })
+            })
+            .filter(|r| r.product_id != product_id)
+            .collect();
+        recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        recommendations
+    }
+}
+
+/// What a product brings in on a receipt that has it, with its usual quantity.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProductValue {
+    pub revenue: f64,
+    pub margin: Option<f64>,
+}
+
+/// How recommendations are ranked: by how likely they are to be bought, or by
+/// the revenue or margin they are expected to add to the receipt.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scoring {
+    #[default]
+    Likelihood,
+    Revenue,
+    Margin,
+}
+
+impl Scoring {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "likelihood" => Some(Scoring::Likelihood),
+            "revenue" => Some(Scoring::Revenue),
+            "margin" => Some(Scoring::Margin),
+            _ => None,
+        }
+    }
+}
+
+/// The production model plus one model per experiment variant that trains
+/// with its own thresholds, and the experiments those variants belong to.
+#[derive(Debug, Clone, Default)]
+pub struct ModelStore {
+    pub primary: RecommendationModel,
+    pub variants: HashMap<i32, RecommendationModel>,
+    pub experiments: Vec<ExperimentWithVariants>,
+    pub counts: BasketCounts,
+    pub visit_patterns: Arc<VisitPatterns>,
+    /// When the model was last mined from scratch rather than refreshed.
+    pub fully_trained_at: Option<Instant>,
+}
+
+impl ModelStore {
+    pub fn len(&self) -> usize {
+        self.primary.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary.is_empty()
+    }
+
+    pub fn model_for(&self, variant: Option<&Variant>) -> &RecommendationModel {
+        variant
+            .and_then(|v| self.variants.get(&v.id))
+            .unwrap_or(&self.primary)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecommendationConfig {
+    pub dayparts: DaypartConfig,
+    /// Age at which a basket counts half as much as the newest one. `None` disables decay.
+    pub half_life_days: Option<f64>,
+    pub min_support: f64,
+    pub min_confidence: f64,
+    /// Receipts a product needs before its own rules fully replace the content-based ones.
+    pub cold_start_receipts: f64,
+    pub scoring: Scoring,
+    /// Quiet time after a sync before the model is refreshed, so bursts of syncs refresh once.
+    pub refresh_debounce: Duration,
+    /// Refreshes older than this re-mine everything, picking up what incremental
+    /// counting leaves alone (similarities, category rules, cold-start matches).
+    pub full_retrain_after: Duration,
+}
+
+impl RecommendationConfig {
+    pub fn from_env() -> Self {
+        let half_life_days = env::var("RECOMMENDATION_HALF_LIFE_DAYS")
+            .ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|days| *days > 0.0);
+
+        RecommendationConfig {
+            dayparts: DaypartConfig::from_env(),
+            half_life_days,
+            min_support: env_f64("RECOMMENDATION_MIN_SUPPORT").unwrap_or(0.01),
+            min_confidence: env_f64("RECOMMENDATION_MIN_CONFIDENCE").unwrap_or(0.1),
+            cold_start_receipts: env_f64("RECOMMENDATION_COLD_START_RECEIPTS").unwrap_or(30.0),
+            scoring: env::var("RECOMMENDATION_SCORING")
+                .ok()
+                .and_then(|v| Scoring::parse(&v))
+                .unwrap_or_default(),
+            refresh_debounce: Duration::from_secs_f64(
+                env_f64("RECOMMENDATION_REFRESH_DEBOUNCE_SECS").unwrap_or(60.0).max(0.0),
+            ),
+            full_retrain_after: Duration::from_secs_f64(
+                env_f64("RECOMMENDATION_FULL_RETRAIN_HOURS").unwrap_or(24.0).max(0.0) * 3600.0,
+            ),
+        }
+    }
+
+    pub fn with_variant(&self, variant: &Variant) -> Self {
+        RecommendationConfig {
+            min_support: variant.min_support.unwrap_or(self.min_support),
+            min_confidence: variant.min_confidence.unwrap_or(self.min_confidence),
+            ..self.clone()
+        }
+    }
+}
+
+fn env_f64(key: &str) -> Option<f64> {
+    env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+pub struct Basket {
+    pub transaction_date: Option<NaiveDateTime>,
+    pub items: Vec<i32>,
+}
+
+/// A receipt that gained sales since the counts were taken, with the items it
+/// was counted with before (empty for a new receipt).
+pub struct BasketChange {
+    pub basket: Basket,
+    pub previous: Vec<i32>,
+}
+
+/// Decayed support counts behind the item-level rules, overall and per
+/// daypart, kept so synced sales can be folded in without re-mining.
+#[derive(Debug, Clone, Default)]
+pub struct BasketCounts {
+    pub overall: SupportCounter<i32>,
+    pub dayparts: HashMap<String, SupportCounter<i32>>,
+    pub newest: Option<NaiveDateTime>,
+    pub oldest: Option<NaiveDateTime>,
+    /// Highest sale already counted; later sales are picked up by a refresh.
+    pub last_sale_id: Option<i32>,
+}
+
+impl BasketCounts {
+    pub fn from_baskets(baskets: &[Basket], config: &RecommendationConfig) -> Self {
+        let dates = baskets.iter().filter_map(|b| b.transaction_date);
+        let mut counts = BasketCounts {
+            newest: dates.clone().max(),
+            oldest: dates.min(),
+            ..Default::default()
+        };
+        for basket in baskets {
+            let weight = counts.weight(basket.transaction_date, config.half_life_days);
+            counts.add(basket, weight, config);
+        }
+        counts
+    }
+
+    /// Ages the existing counts to the newest incoming receipt, then swaps each
+    /// changed receipt's old contents for its new ones.
+    pub fn apply(&mut self, changes: &[BasketChange], config: &RecommendationConfig) {
+        let dates = changes.iter().filter_map(|c| c.basket.transaction_date);
+        if let Some(latest) = dates.clone().max() {
+            if let (Some(half_life_days), Some(newest)) = (config.half_life_days, self.newest) {
+                if latest > newest {
+                    let days = (latest - newest).num_seconds() as f64 / 86_400.0;
+                    let factor = 0.5f64.powf(days / half_life_days);
+                    self.overall.decay(factor);
+                    for counter in self.dayparts.values_mut() {
+                        counter.decay(factor);
+                    }
+                }
+            }
+            self.newest = self.newest.max(Some(latest));
+            self.oldest = self.oldest.into_iter().chain(dates.min()).min();
+        }
+
+        for change in changes {
+            let weight = self.weight(change.basket.transaction_date, config.half_life_days);
+            if !change.previous.is_empty() {
+                let previous = Basket {
+                    transaction_date: change.basket.transaction_date,
+                    items: change.previous.clone(),
+                };
+                self.remove(&previous, weight, config);
+            }
+            self.add(&change.basket, weight, config);
+        }
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.overall.transactions().raw
+    }
+
+    // Same decay as `basket_weights`: relative to the newest basket, with
+    // undated baskets treated as the oldest.
+    fn weight(&self, date: Option<NaiveDateTime>, half_life_days: Option<f64>) -> f64 {
+        let (Some(half_life_days), Some(newest), Some(date)) =
+            (half_life_days, self.newest, date.or(self.oldest))
+        else {
+            return 1.0;
+        };
+        let age_days = (newest - date).num_seconds() as f64 / 86_400.0;
+        0.5f64.powf(age_days / half_life_days)
+    }
+
+    fn add(&mut self, basket: &Basket, weight: f64, config: &RecommendationConfig) {
+        self.overall.add(&basket.items, weight);
+        if let Some(segment) = basket
+            .transaction_date
+            .and_then(|date| config.dayparts.segment_for(date))
+        {
+            self.dayparts.entry(segment).or_default().add(&basket.items, weight);
+        }
+    }
+
+    fn remove(&mut self, basket: &Basket, weight: f64, config: &RecommendationConfig) {
+        self.overall.remove(&basket.items, weight);
+        if let Some(counter) = basket
+            .transaction_date
+            .and_then(|date| config.dayparts.segment_for(date))
+            .and_then(|segment| self.dayparts.get_mut(&segment))
+        {
+            counter.remove(&basket.items, weight);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CustomerHistory {
+    pub receipts: i64,
+    pub items: Vec<(i32, f64)>,
 }
 
 pub async fn train_and_cache_recommendations(
     pool: &PgPool,
-) -> Result<HashMap<i32, Vec<Recommendation>>, sqlx::Error> {
+    config: &RecommendationConfig,
+) -> anyhow::Result<ModelStore> {
     println!("Starting recommendation model training...");
 
-    let transactions = get_transactions(pool).await?;
-    if transactions.is_empty() {
+    let experiments = experiments::get_experiments(pool, true).await?;
+
+    let (baskets, last_sale_id) = get_transactions_with_last_sale(pool).await?;
+    if baskets.is_empty() {
         println!("No transactions found to train recommendation model.");
-        return Ok(HashMap::new());
+        return Ok(ModelStore {
+            experiments,
+            ..Default::default()
+        });
     }
 
-    // Use the external apriori module
-    let rules = apriori::apriori(&transactions, 0.01, 0.1);
-    println!("Apriori algorithm generated {} rules.", rules.len());
+    let products = get_product_catalog(pool).await?;
+    let mut counts = BasketCounts::from_baskets(&baskets, config);
+    counts.last_sale_id = last_sale_id;
+    let mut model = build_rule_model(&baskets, &counts, &products, config);
 
-    let mut recommendation_map: HashMap<i32, Vec<Recommendation>> = HashMap::new();
-    for rule in rules {
-        if rule.lhs.len() == 1 {
-            let antecedent = rule.lhs[0];
-            for consequent in &rule.rhs {
-                let recommendation = Recommendation {
-                    product_id: *consequent,
-                    score: rule.confidence,
-                };
-                recommendation_map
-                    .entry(antecedent)
-                    .or_default()
-                    .push(recommendation);
+    let feedback = feedback::load_feedback_stats(pool).await?;
+    apply_feedback(&mut model, &feedback);
+
+    model.product_values = get_product_values(pool).await?;
+
+    let histories: Vec<Vec<(i32, f64)>> = get_customer_histories(pool).await?.into_values().collect();
+    model.item_similarities =
+        collaborative::item_similarities(&histories, SIMILAR_ITEMS_PER_PRODUCT);
+    println!(
+        "Item similarities computed from {} customer histories ({} items).",
+        histories.len(),
+        model.item_similarities.len()
+    );
+
+    let version = record_model_version(pool, &model, baskets.len()).await?;
+    model.version = Some(version);
+
+    let mut variants = HashMap::new();
+    for variant in experiments.iter().flat_map(|e| &e.variants) {
+        if !variant.overrides_thresholds() {
+            continue;
+        }
+        println!("Training experiment variant '{}'...", variant.name);
+        let mut variant_model =
+            build_rule_model(&baskets, &counts, &products, &config.with_variant(variant));
+        apply_feedback(&mut variant_model, &feedback);
+        variant_model.item_similarities = model.item_similarities.clone();
+        variant_model.product_values = model.product_values.clone();
+        variant_model.version = model.version;
+        variants.insert(variant.id, variant_model);
+    }
+
+    let visit_patterns = Arc::new(next_visit::mine_visit_patterns(pool).await?);
+
+    println!("Recommendation model training complete (version {}).", version);
+    Ok(ModelStore {
+        primary: model,
+        variants,
+        experiments,
+        counts,
+        visit_patterns,
+        fully_trained_at: Some(Instant::now()),
+    })
+}
+
+/// Folds sales synced since `current` was trained into its support counts and
+/// rebuilds the item-level rules from them, leaving the slower parts of the
+/// model as they are. `None` when no new sales have arrived.
+pub async fn refresh_recommendations(
+    pool: &PgPool,
+    config: &RecommendationConfig,
+    current: ModelStore,
+) -> anyhow::Result<Option<ModelStore>> {
+    let Some(last_sale_id) = current.counts.last_sale_id else {
+        return Ok(None);
+    };
+    let (changes, newest_sale_id) = get_basket_changes(pool, last_sale_id).await?;
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    println!("Refreshing recommendation model with {} changed receipts...", changes.len());
+
+    let ModelStore {
+        mut primary,
+        variants: mut previous_variants,
+        experiments,
+        mut counts,
+        fully_trained_at,
+        ..
+    } = current;
+    counts.apply(&changes, config);
+    counts.last_sale_id = newest_sale_id.max(Some(last_sale_id));
+
+    let feedback = feedback::load_feedback_stats(pool).await?;
+    build_item_rules(&mut primary, &counts, config);
+    apply_feedback(&mut primary, &feedback);
+    let version = record_model_version(pool, &primary, counts.transaction_count()).await?;
+    primary.version = Some(version);
+
+    let mut variants = HashMap::new();
+    for variant in experiments.iter().flat_map(|e| &e.variants) {
+        let Some(mut variant_model) = previous_variants.remove(&variant.id) else {
+            continue;
+        };
+        build_item_rules(&mut variant_model, &counts, &config.with_variant(variant));
+        apply_feedback(&mut variant_model, &feedback);
+        variant_model.version = primary.version;
+        variants.insert(variant.id, variant_model);
+    }
+
+    let visit_patterns = Arc::new(next_visit::mine_visit_patterns(pool).await?);
+
+    println!("Recommendation model refreshed (version {}).", version);
+    Ok(Some(ModelStore {
+        primary,
+        variants,
+        experiments,
+        counts,
+        visit_patterns,
+        fully_trained_at,
+    }))
+}
+
+async fn record_model_version(
+    pool: &PgPool,
+    model: &RecommendationModel,
+    transaction_count: usize,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO recommendation_model_versions (transaction_count, items_cached, daypart_count)
+         VALUES ($1, $2, $3)
+         RETURNING id",
+        transaction_count as i32,
+        model.len() as i32,
+        model.dayparts.len() as i32
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Mines the overall and per-daypart association rules from `baskets`, plus
+/// category- and department-level rules for items without rules of their own.
+pub fn build_rule_model(
+    baskets: &[Basket],
+    counts: &BasketCounts,
+    products: &HashMap<i32, ProductInfo>,
+    config: &RecommendationConfig,
+) -> RecommendationModel {
+    let mut model = RecommendationModel {
+        cold_start_receipts: config.cold_start_receipts,
+        ..Default::default()
+    };
+    build_item_rules(&mut model, counts, config);
+
+    let transactions: Vec<Vec<i32>> = baskets.iter().map(|b| b.items.clone()).collect();
+    let weights = basket_weights(baskets, config.half_life_days);
+
+    let level = |pick: fn(&ProductInfo) -> &Option<String>| -> HashMap<i32, String> {
+        products
+            .iter()
+            .filter_map(|(id, levels)| Some((*id, pick(levels).clone()?)))
+            .collect()
+    };
+    let categories = LevelRules::build(
+        ExplanationSource::CategoryRule,
+        &transactions,
+        &weights,
+        level(|p| &p.category),
+        config,
+    );
+    let departments = LevelRules::build(
+        ExplanationSource::DepartmentRule,
+        &transactions,
+        &weights,
+        level(|p| &p.department),
+        config,
+    );
+    println!(
+        "Level rules: {} categories and {} departments have rules.",
+        categories.len(),
+        departments.len()
+    );
+
+    model.content_neighbours = build_content_neighbours(
+        products,
+        &model.receipt_counts,
+        &model.overall,
+        config.cold_start_receipts,
+    );
+    println!(
+        "Content-based neighbours computed for {} cold-start products.",
+        model.content_neighbours.len()
+    );
+
+    model.categories = categories;
+    model.departments = departments;
+    model
+}
+
+/// Overall and per-daypart rules, read off the support counts.
+fn build_item_rules(model: &mut RecommendationModel, counts: &BasketCounts, config: &RecommendationConfig) {
+    model.overall = build_recommendation_map(&counts.overall, config, None);
+
+    model.dayparts = HashMap::new();
+    for (segment, counter) in &counts.dayparts {
+        let transactions = counter.transactions().raw;
+        if transactions < MIN_SEGMENT_TRANSACTIONS {
+            println!(
+                "Skipping daypart '{}': only {} transactions.",
+                segment, transactions
+            );
+            continue;
+        }
+        let recommendation_map = build_recommendation_map(counter, config, Some(segment));
+        println!(
+            "Daypart '{}' trained on {} transactions ({} items cached).",
+            segment,
+            transactions,
+            recommendation_map.len()
+        );
+        model.dayparts.insert(segment.clone(), recommendation_map);
+    }
+
+    model.receipt_counts = counts
+        .overall
+        .item_counts()
+        .map(|(id, count)| (*id, count.raw))
+        .collect();
+    model.training_window = counts.oldest.zip(counts.newest);
+}
+
+// Products sold on fewer than `cold_start_receipts` receipts are matched, by
+// name, category, department, unit and price, to products that have rules.
+fn build_content_neighbours(
+    products: &HashMap<i32, ProductInfo>,
+    receipt_counts: &HashMap<i32, usize>,
+    rules: &RecommendationMap,
+    cold_start_receipts: f64,
+) -> HashMap<i32, Vec<(i32, f64)>> {
+    let features = |product: &ProductInfo| {
+        content::product_features(
+            product.name.as_deref(),
+            product.category.as_deref(),
+            product.department.as_deref(),
+            product.unit.as_deref(),
+            product.selling_price.map(|p| p as f64),
+        )
+    };
+
+    let items: Vec<(i32, Vec<(String, f64)>)> = products
+        .iter()
+        .map(|(id, product)| (*id, features(product)))
+        .collect();
+    let queries: HashSet<i32> = products
+        .keys()
+        .filter(|id| (receipt_counts.get(id).copied().unwrap_or(0) as f64) < cold_start_receipts)
+        .copied()
+        .collect();
+    let candidates: HashSet<i32> = products
+        .keys()
+        .filter(|id| rules.contains_key(id))
+        .copied()
+        .collect();
+
+    content::similarities(&items, &queries, &candidates, CONTENT_NEIGHBOURS)
+}
+
+/// Re-ranks every rule by how well it has converted on the POS.
+fn apply_feedback(model: &mut RecommendationModel, feedback: &FeedbackStats) {
+    let maps = std::iter::once(&mut model.overall).chain(model.dayparts.values_mut());
+    for recommendation_map in maps {
+        for (antecedent, recommendations) in recommendation_map.iter_mut() {
+            for recommendation in recommendations.iter_mut() {
+                recommendation.score *= feedback.boost(*antecedent, recommendation.product_id);
             }
+            recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
         }
     }
+}
+
+/// Exponential time decay relative to the newest basket, so the weights do not
+/// drift with the wall clock. Undated baskets are treated as the oldest ones.
+fn basket_weights(baskets: &[Basket], half_life_days: Option<f64>) -> Vec<f64> {
+    let Some(half_life_days) = half_life_days else {
+        return vec![1.0; baskets.len()];
+    };
+
+    let dates = baskets.iter().filter_map(|b| b.transaction_date);
+    let (Some(newest), Some(oldest)) = (dates.clone().max(), dates.min()) else {
+        return vec![1.0; baskets.len()];
+    };
+
+    baskets
+        .iter()
+        .map(|basket| {
+            let date = basket.transaction_date.unwrap_or(oldest);
+            let age_days = (newest - date).num_seconds() as f64 / 86_400.0;
+            0.5f64.powf(age_days / half_life_days)
+        })
+        .collect()
+}
+
+fn build_recommendation_map(
+    counter: &SupportCounter<i32>,
+    config: &RecommendationConfig,
+    daypart: Option<&str>,
+) -> RecommendationMap {
+    let rules = counter.rules(config.min_support, config.min_confidence);
+    println!("Support counts gave {} rules.", rules.len());
+
+    let mut recommendation_map: RecommendationMap = HashMap::new();
+    for rule in rules {
+        let counts = RuleCounts {
+            together: rule.together,
+            antecedent: rule.antecedent_count,
+            total: rule.total,
+        };
+        let explanation =
+            Explanation::rule(rule.antecedent, counts, rule.confidence, rule.lift, daypart);
+        let recommendation =
+            Recommendation::new(rule.consequent, rule.confidence).explained(explanation);
+        recommendation_map
+            .entry(rule.antecedent)
+            .or_default()
+            .push(recommendation);
+    }
 
     for recommendations in recommendation_map.values_mut() {
         recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
     }
 
-    println!("Recommendation model training complete.");
-    Ok(recommendation_map)
+    recommendation_map
+}
+
+// Plain receipt counts behind single-antecedent rules, for explanations; the
+// rule supports themselves are decayed and not something a manager can check.
+fn rule_counts<T: Eq + Hash + Clone>(
+    transactions: &[Vec<T>],
+    rules: &[apriori::Rule<T>],
+) -> HashMap<(T, T), RuleCounts> {
+    let mut consequents: HashMap<&T, HashSet<&T>> = HashMap::new();
+    for rule in rules {
+        consequents.entry(&rule.lhs[0]).or_default().extend(&rule.rhs);
+    }
+
+    let mut antecedent_counts: HashMap<&T, usize> = HashMap::new();
+    let mut together: HashMap<(&T, &T), usize> = HashMap::new();
+    for transaction in transactions {
+        let items: HashSet<&T> = transaction.iter().collect();
+        for item in &items {
+            let Some(targets) = consequents.get(item) else {
+                continue;
+            };
+            *antecedent_counts.entry(item).or_insert(0) += 1;
+            for target in targets.iter().filter(|t| items.contains(*t)) {
+                *together.entry((item, target)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    consequents
+        .iter()
+        .flat_map(|(antecedent, targets)| targets.iter().map(move |target| (*antecedent, *target)))
+        .map(|(antecedent, target)| {
+            let counts = RuleCounts {
+                together: together.get(&(antecedent, target)).copied().unwrap_or(0),
+                antecedent: antecedent_counts.get(antecedent).copied().unwrap_or(0),
+                total: transactions.len(),
+            };
+            ((antecedent.clone(), target.clone()), counts)
+        })
+        .collect()
 }
 
+/// Looks up the daypart model first and falls back to the overall rules when
+/// the daypart has no model or no rules for this product. Products without any
+/// item-level rule get their category's rules, then their department's.
 pub fn get_recommendations_from_cache(
-    cache: &HashMap<i32, Vec<Recommendation>>,
+    model: &RecommendationModel,
+    product_id: i32,
+    daypart: Option<&str>,
+) -> Vec<Recommendation> {
+    let item_level = item_rules(model, product_id, daypart);
+
+    let handover = if model.cold_start_receipts > 0.0 {
+        let receipts = model.receipt_counts.get(&product_id).copied().unwrap_or(0) as f64;
+        (receipts / model.cold_start_receipts).min(1.0)
+    } else {
+        1.0
+    };
+    if handover < 1.0 {
+        let content_based = content_recommendations(model, product_id, daypart);
+        if !content_based.is_empty() {
+            return blend(item_level.cloned().unwrap_or_default(), content_based, handover);
+        }
+    }
+
+    if let Some(recommendations) = item_level {
+        return recommendations.clone();
+    }
+
+    let by_category = model.categories.recommend(product_id);
+    if !by_category.is_empty() {
+        return by_category;
+    }
+    model.departments.recommend(product_id)
+}
+
+fn item_rules<'a>(
+    model: &'a RecommendationModel,
     product_id: i32,
+    daypart: Option<&str>,
+) -> Option<&'a Vec<Recommendation>> {
+    daypart
+        .and_then(|segment| model.dayparts.get(segment))
+        .and_then(|cache| cache.get(&product_id))
+        .or_else(|| model.overall.get(&product_id))
+}
+
+/// Borrows the rules of the products that look most like this one, each scored
+/// by how similar the borrowing product is.
+fn content_recommendations(
+    model: &RecommendationModel,
+    product_id: i32,
+    daypart: Option<&str>,
+) -> Vec<Recommendation> {
+    let mut best: HashMap<i32, Recommendation> = HashMap::new();
+    for (neighbour, similarity) in model.content_neighbours.get(&product_id).into_iter().flatten() {
+        for recommendation in item_rules(model, *neighbour, daypart).into_iter().flatten() {
+            if recommendation.product_id == product_id {
+                continue;
+            }
+            let score = similarity * recommendation.score;
+            if best.get(&recommendation.product_id).is_some_and(|b| b.score >= score) {
+                continue;
+            }
+            let explanation = Explanation::content_based(
+                *neighbour,
+                *similarity,
+                recommendation.explanation.as_ref(),
+            );
+            best.insert(
+                recommendation.product_id,
+                Recommendation::new(recommendation.product_id, score).explained(explanation),
+            );
+        }
+    }
+
+    let mut recommendations: Vec<Recommendation> = best.into_values().collect();
+    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    recommendations
+}
+
+// Weighted mix of a product's own rules and the content-based ones; `handover`
+// grows from 0 to 1 with the product's sales. Each result keeps the
+// explanation of whichever side contributed more to its score.
+fn blend(
+    item_level: Vec<Recommendation>,
+    content_based: Vec<Recommendation>,
+    handover: f64,
+) -> Vec<Recommendation> {
+    let mut blended: HashMap<i32, (Recommendation, f64)> = HashMap::new();
+    let weighted = item_level
+        .into_iter()
+        .map(|r| (handover, r))
+        .chain(content_based.into_iter().map(|r| (1.0 - handover, r)));
+    for (weight, recommendation) in weighted {
+        let contribution = weight * recommendation.score;
+        match blended.get_mut(&recommendation.product_id) {
+            Some((existing, largest)) => {
+                existing.score += contribution;
+                if contribution > *largest {
+                    existing.explanation = recommendation.explanation;
+                    *largest = contribution;
+                }
+            }
+            None => {
+                let mut entry = recommendation;
+                entry.score = contribution;
+                blended.insert(entry.product_id, (entry, contribution));
+            }
+        }
+    }
+
+    let mut recommendations: Vec<Recommendation> =
+        blended.into_values().map(|(recommendation, _)| recommendation).collect();
+    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    recommendations
+}
+
+/// Personalized recommendations from the customer's purchase history. Customers
+/// with too few receipts, or when `personalized` is off, get the association
+/// rules for their usual items.
+pub fn get_customer_recommendations_from_cache(
+    model: &RecommendationModel,
+    history: &CustomerHistory,
+    personalized: bool,
 ) -> Vec<Recommendation> {
-    cache.get(&product_id).cloned().unwrap_or_default()
+    if personalized && history.receipts >= MIN_CUSTOMER_RECEIPTS {
+        let personalized = collaborative::recommend(
+            &model.item_similarities,
+            &history.items,
+            CUSTOMER_RECOMMENDATION_LIMIT,
+        );
+        if !personalized.is_empty() {
+            return personalized
+                .into_iter()
+                .map(|(product_id, score)| {
+                    Recommendation::new(product_id, score).explained(Explanation::collaborative())
+                })
+                .collect();
+        }
+    }
+
+    let owned: Vec<i32> = history.items.iter().map(|(product_id, _)| *product_id).collect();
+    recommend_for_basket(model, &owned, None, CUSTOMER_RECOMMENDATION_LIMIT)
+}
+
+/// Combines the rules of every item in `basket`, keeping each candidate's best
+/// score and skipping anything already in the basket.
+pub fn recommend_for_basket(
+    model: &RecommendationModel,
+    basket: &[i32],
+    daypart: Option<&str>,
+    limit: usize,
+) -> Vec<Recommendation> {
+    let mut best: HashMap<i32, Recommendation> = HashMap::new();
+    for product_id in basket {
+        for recommendation in get_recommendations_from_cache(model, *product_id, daypart) {
+            if basket.contains(&recommendation.product_id) {
+                continue;
+            }
+            let entry = best
+                .entry(recommendation.product_id)
+                .or_insert_with(|| recommendation.clone());
+            if recommendation.score > entry.score {
+                *entry = recommendation;
+            }
+        }
+    }
+
+    let mut recommendations: Vec<Recommendation> = best.into_values().collect();
+    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    recommendations.truncate(limit);
+    recommendations
+}
+
+/// Re-ranks by expected incremental revenue or margin: the likelihood score
+/// times what the product is worth on a receipt. Products without a known
+/// value drop to the bottom rather than out of the list.
+pub fn apply_scoring(
+    model: &RecommendationModel,
+    recommendations: &mut [Recommendation],
+    scoring: Scoring,
+) {
+    if scoring == Scoring::Likelihood {
+        return;
+    }
+    for recommendation in recommendations.iter_mut() {
+        let value = model.product_values.get(&recommendation.product_id);
+        let worth = match scoring {
+            Scoring::Revenue => value.map(|v| v.revenue),
+            Scoring::Margin => value.and_then(|v| v.margin),
+            Scoring::Likelihood => None,
+        };
+        recommendation.score *= worth.unwrap_or(0.0);
+    }
+    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
 }
 
-async fn get_transactions(pool: &PgPool) -> Result<Vec<Vec<i32>>, sqlx::Error> {
+/// Stamps the model version and training window on each explanation, or drops
+/// the explanations when they were not asked for.
+pub fn explain(model: &RecommendationModel, recommendations: &mut [Recommendation], explain: bool) {
+    for recommendation in recommendations {
+        if !explain {
+            recommendation.explanation = None;
+        } else if let Some(explanation) = recommendation.explanation.as_mut() {
+            explanation.model_version = model.version;
+            explanation.trained_from = model.training_window.map(|(from, _)| from);
+            explanation.trained_to = model.training_window.map(|(_, to)| to);
+        }
+    }
+}
+
+// Revenue per receipt uses what was actually charged, so discounts and the
+// usual quantity (three plates, not one) are included. Products without a cost
+// price get the average margin ratio of those that have one.
+async fn get_product_values(pool: &PgPool) -> Result<HashMap<i32, ProductValue>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.product_id AS "product_id!",
+               SUM(COALESCE(NULLIF(s.total_sale, 0), s.quantity * s.selling_price))::FLOAT8
+                   / COUNT(DISTINCT s.receipt_id) AS revenue,
+               SUM(s.quantity)::FLOAT8 / COUNT(DISTINCT s.receipt_id) AS quantity,
+               MAX(p.cost_price)::FLOAT8 AS cost_price
+        FROM sales s
+        LEFT JOIN products p ON p.product_id = s.product_id
+        WHERE s.product_id IS NOT NULL AND s.receipt_id IS NOT NULL
+        GROUP BY s.product_id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut values: HashMap<i32, ProductValue> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let revenue = row.revenue.filter(|r| *r > 0.0)?;
+            let margin = match (row.quantity, row.cost_price.filter(|c| *c > 0.0)) {
+                (Some(quantity), Some(cost)) => Some(revenue - quantity * cost),
+                _ => None,
+            };
+            Some((row.product_id, ProductValue { revenue, margin }))
+        })
+        .collect();
+
+    let (known_margin, known_revenue) = values
+        .values()
+        .filter_map(|v| Some((v.margin?, v.revenue)))
+        .fold((0.0, 0.0), |(m, r), (margin, revenue)| (m + margin, r + revenue));
+    if known_revenue > 0.0 {
+        let ratio = known_margin / known_revenue;
+        for value in values.values_mut().filter(|v| v.margin.is_none()) {
+            value.margin = Some(value.revenue * ratio);
+        }
+    }
+
+    Ok(values)
+}
+
+pub async fn get_product_catalog(
+    pool: &PgPool,
+) -> Result<HashMap<i32, ProductInfo>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT product_id, name, category, department, unit, selling_price FROM products"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let product = ProductInfo {
+                name: row.name,
+                category: row.category.filter(|c| !c.trim().is_empty()),
+                department: row.department.filter(|d| !d.trim().is_empty()),
+                unit: row.unit.filter(|u| !u.trim().is_empty()),
+                selling_price: row.selling_price,
+            };
+            (row.product_id, product)
+        })
+        .collect())
+}
+
+pub async fn get_customer_history(
+    pool: &PgPool,
+    customer_id: i32,
+) -> Result<CustomerHistory, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT s.product_id, COUNT(DISTINCT s.receipt_id) AS receipts
+         FROM sales s
+         JOIN receipts r ON s.receipt_id = r.receipt_id
+         WHERE r.customer_id = $1 AND s.product_id IS NOT NULL
+         GROUP BY s.product_id
+         ORDER BY receipts DESC",
+        customer_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let receipts = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM receipts WHERE customer_id = $1",
+        customer_id
+    )
+    .fetch_one(pool)
+    .await?
+    .unwrap_or(0);
+
+    let items = rows
+        .into_iter()
+        .filter_map(|row| Some((row.product_id?, implicit_weight(row.receipts.unwrap_or(0)))))
+        .collect();
+
+    Ok(CustomerHistory { receipts, items })
+}
+
+async fn get_customer_histories(
+    pool: &PgPool,
+) -> Result<HashMap<i32, Vec<(i32, f64)>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT r.customer_id, s.product_id, COUNT(DISTINCT s.receipt_id) AS receipts
+         FROM sales s
+         JOIN receipts r ON s.receipt_id = r.receipt_id
+         WHERE r.customer_id IS NOT NULL AND s.product_id IS NOT NULL
+         GROUP BY r.customer_id, s.product_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut histories: HashMap<i32, Vec<(i32, f64)>> = HashMap::new();
+    for row in rows {
+        if let (Some(customer_id), Some(product_id)) = (row.customer_id, row.product_id) {
+            histories
+                .entry(customer_id)
+                .or_default()
+                .push((product_id, implicit_weight(row.receipts.unwrap_or(0))));
+        }
+    }
+
+    Ok(histories)
+}
+
+// Repeat purchases count, but with diminishing returns.
+fn implicit_weight(receipts: i64) -> f64 {
+    (1.0 + receipts as f64).ln()
+}
+
+/// Fills in product details for cached recommendations and drops anything the
+/// kitchen cannot serve right now (out of stock or below its restock level).
+pub async fn enrich_recommendations(
+    pool: &PgPool,
+    recommendations: Vec<Recommendation>,
+) -> Result<Vec<Recommendation>, sqlx::Error> {
+    if recommendations.is_empty() {
+        return Ok(recommendations);
+    }
+
+    let product_ids: Vec<i32> = recommendations.iter().map(|r| r.product_id).collect();
+    let rows = sqlx::query!(
+        "SELECT product_id, name, category, department, selling_price
+         FROM products
+         WHERE product_id = ANY($1)
+           AND (current_stock IS NULL
+                OR (current_stock > 0 AND current_stock >= COALESCE(restock_level, 0)))",
+        &product_ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut details: HashMap<i32, _> = rows.into_iter().map(|row| (row.product_id, row)).collect();
+
+    Ok(recommendations
+        .into_iter()
+        .filter_map(|mut recommendation| {
+            let row = details.remove(&recommendation.product_id)?;
+            recommendation.name = row.name;
+            recommendation.category = row.category;
+            recommendation.department = row.department;
+            recommendation.selling_price = row.selling_price;
+            Some(recommendation)
+        })
+        .collect())
+}
+
+pub async fn get_transactions(pool: &PgPool) -> Result<Vec<Basket>, sqlx::Error> {
+    Ok(get_transactions_with_last_sale(pool).await?.0)
+}
+
+// The baskets plus the highest sale they include, so a later refresh knows
+// where to pick up.
+async fn get_transactions_with_last_sale(
+    pool: &PgPool,
+) -> Result<(Vec<Basket>, Option<i32>), sqlx::Error> {
     // Ensure we only get valid sales with both receipt and product IDs
-    let rows = sqlx::query!("SELECT receipt_id, product_id FROM sales WHERE receipt_id IS NOT NULL AND product_id IS NOT NULL")
-        .fetch_all(pool)
-        .await?;
+    let rows = sqlx::query!(
+        "SELECT s.sale_id, s.receipt_id, s.product_id, r.transaction_date
+         FROM sales s
+         JOIN receipts r ON s.receipt_id = r.receipt_id
+         WHERE s.receipt_id IS NOT NULL AND s.product_id IS NOT NULL"
+    )
+    .fetch_all(pool)
+    .await?;
 
-    let mut transactions_map: HashMap<i32, Vec<i32>> = HashMap::new();
+    let last_sale_id = rows.iter().map(|row| row.sale_id).max();
+    let mut transactions_map: HashMap<i32, Basket> = HashMap::new();
     for row in rows {
         if let (Some(receipt_id), Some(product_id)) = (row.receipt_id, row.product_id) {
             transactions_map
                 .entry(receipt_id)
-                .or_default()
+                .or_insert_with(|| Basket {
+                    transaction_date: row.transaction_date,
+                    items: Vec::new(),
+                })
+                .items
                 .push(product_id);
         }
     }
 
-    Ok(transactions_map.into_values().collect())
-}
\ No newline at end of file
+    Ok((transactions_map.into_values().collect(), last_sale_id))
+}
+
+/// Every receipt with a sale after `after_sale_id`, split into what was
+/// already counted and what it holds now. Also returns the newest sale seen.
+async fn get_basket_changes(
+    pool: &PgPool,
+    after_sale_id: i32,
+) -> Result<(Vec<BasketChange>, Option<i32>), sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT s.sale_id, s.receipt_id, s.product_id, r.transaction_date
+         FROM sales s
+         JOIN receipts r ON s.receipt_id = r.receipt_id
+         WHERE s.product_id IS NOT NULL
+           AND s.receipt_id IN (SELECT receipt_id FROM sales WHERE sale_id > $1)",
+        after_sale_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let newest_sale_id = rows.iter().map(|row| row.sale_id).max();
+    let mut changes: HashMap<i32, BasketChange> = HashMap::new();
+    for row in rows {
+        if let (Some(receipt_id), Some(product_id)) = (row.receipt_id, row.product_id) {
+            let change = changes.entry(receipt_id).or_insert_with(|| BasketChange {
+                basket: Basket {
+                    transaction_date: row.transaction_date,
+                    items: Vec::new(),
+                },
+                previous: Vec::new(),
+            });
+            change.basket.items.push(product_id);
+            if row.sale_id <= after_sale_id {
+                change.previous.push(product_id);
+            }
+        }
+    }
+
+    Ok((changes.into_values().collect(), newest_sale_id))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recommendation(product_id: i32, score: f64, source: ExplanationSource) -> Recommendation {
+        Recommendation::new(product_id, score).explained(Explanation {
+            source,
+            ..Default::default()
+        })
+    }
+
+    fn scores(recommendations: &[Recommendation]) -> Vec<(i32, f64)> {
+        recommendations.iter().map(|r| (r.product_id, r.score)).collect()
+    }
+
+    #[test]
+    fn blend_weights_each_side_by_the_handover() {
+        let item_level = vec![
+            recommendation(10, 1.0, ExplanationSource::AssociationRule),
+            recommendation(11, 0.4, ExplanationSource::AssociationRule),
+        ];
+        let content_based = vec![
+            recommendation(10, 0.5, ExplanationSource::ContentBased),
+            recommendation(12, 0.8, ExplanationSource::ContentBased),
+        ];
+
+        let blended = blend(item_level.clone(), content_based.clone(), 0.25);
+        let found = scores(&blended);
+        let expected = [(10, 0.625), (12, 0.6), (11, 0.1)];
+        assert_eq!(found.len(), expected.len());
+        for ((id, score), (expected_id, expected_score)) in found.into_iter().zip(expected) {
+            assert_eq!(id, expected_id);
+            assert!((score - expected_score).abs() < 1e-9);
+        }
+        // 0.375 of product 10's score came from the content side.
+        let source = |r: &Recommendation| r.explanation.as_ref().unwrap().source;
+        assert_eq!(source(&blended[0]), ExplanationSource::ContentBased);
+        assert_eq!(source(&blended[2]), ExplanationSource::AssociationRule);
+
+        let handed_over = blend(item_level, content_based, 1.0);
+        assert_eq!(handed_over[0].product_id, 10);
+        assert!((handed_over[0].score - 1.0).abs() < 1e-9);
+        assert_eq!(source(&handed_over[0]), ExplanationSource::AssociationRule);
+    }
+
+    #[test]
+    fn rarely_sold_products_borrow_rules_from_similar_ones() {
+        let mut model = RecommendationModel {
+            cold_start_receipts: 40.0,
+            ..Default::default()
+        };
+        model.overall.insert(1, vec![Recommendation::new(6, 1.0)]);
+        model
+            .overall
+            .insert(2, vec![Recommendation::new(5, 0.8), Recommendation::new(1, 0.9)]);
+        model.content_neighbours.insert(1, vec![(2, 0.5)]);
+        model.receipt_counts.insert(1, 10);
+
+        // A quarter of the way to handover: the own rule scores 0.25 and the
+        // borrowed one 0.75 * 0.5 * 0.8; the rule back to product 1 is skipped.
+        let recommendations = get_recommendations_from_cache(&model, 1, None);
+        let found = scores(&recommendations);
+        assert_eq!(found.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![5, 6]);
+        assert!((found[0].1 - 0.3).abs() < 1e-9);
+        assert!((found[1].1 - 0.25).abs() < 1e-9);
+        let explanation = recommendations[0].explanation.as_ref().unwrap();
+        assert_eq!(explanation.source, ExplanationSource::ContentBased);
+        assert_eq!(explanation.similarity, Some(0.5));
+
+        model.receipt_counts.insert(1, 40);
+        let recommendations = get_recommendations_from_cache(&model, 1, None);
+        assert_eq!(scores(&recommendations), vec![(6, 1.0)]);
+    }
+}
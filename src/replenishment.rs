@@ -0,0 +1,504 @@
+use crate::calendar;
+use crate::errors::RequestError;
+use crate::forecast_accuracy;
+use crate::forecasting::{self, ForecastOptions};
+use crate::ml::forecasting::normal_quantile;
+use crate::recipes;
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+const UNASSIGNED_SUPPLIER: &str = "Unassigned";
+// Lead times and review periods must fit inside the forecast horizon, or the
+// demand they cover would be cut off.
+const MAX_PLANNING_DAYS: f64 = (forecasting::MAX_HORIZON - 1) as f64;
+
+#[derive(Debug, Clone)]
+pub struct ReplenishmentConfig {
+    /// Days between placing an order and the stock arriving.
+    pub lead_time_days: f64,
+    /// Per-supplier lead times overriding the default, keyed by supplier name.
+    pub supplier_lead_times: HashMap<String, f64>,
+    /// Chance of not running out before the order arrives, e.g. 0.95.
+    pub service_level: f64,
+    /// Days an order has to last until the next one is placed.
+    pub review_days: f64,
+}
+
+impl ReplenishmentConfig {
+    /// Reads `REPLENISHMENT_LEAD_TIME_DAYS`, `REPLENISHMENT_SERVICE_LEVEL`,
+    /// `REPLENISHMENT_REVIEW_DAYS` and `REPLENISHMENT_SUPPLIER_LEAD_TIMES`,
+    /// the last as e.g. `Kenya Beverages:1,Nairobi Fresh Foods:3`.
+    pub fn from_env() -> Self {
+        let env_f64 = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|v| v.is_finite())
+        };
+        let supplier_lead_times = env::var("REPLENISHMENT_SUPPLIER_LEAD_TIMES")
+            .map(|spec| {
+                spec.split(',')
+                    .filter_map(|entry| {
+                        let (supplier, days) = entry.rsplit_once(':')?;
+                        let days = days.trim().parse::<f64>().ok().filter(|d| d.is_finite())?;
+                        Some((supplier.trim().to_string(), days.clamp(0.0, MAX_PLANNING_DAYS)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        ReplenishmentConfig {
+            lead_time_days: env_f64("REPLENISHMENT_LEAD_TIME_DAYS")
+                .unwrap_or(2.0)
+                .clamp(0.0, MAX_PLANNING_DAYS),
+            supplier_lead_times,
+            service_level: env_f64("REPLENISHMENT_SERVICE_LEVEL")
+                .unwrap_or(0.95)
+                .clamp(0.5, 0.999),
+            review_days: env_f64("REPLENISHMENT_REVIEW_DAYS")
+                .unwrap_or(1.0)
+                .clamp(0.0, MAX_PLANNING_DAYS),
+        }
+    }
+
+    pub fn lead_time_for(&self, supplier: &str) -> f64 {
+        self.supplier_lead_times
+            .get(supplier)
+            .copied()
+            .unwrap_or(self.lead_time_days)
+    }
+}
+
+/// The supplier a product is ordered from, with products that have none
+/// grouped under one name so they share its lead time everywhere.
+pub fn supplier_name(supplier: Option<&str>) -> &str {
+    supplier
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(UNASSIGNED_SUPPLIER)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplenishmentOptions {
+    /// Overrides every supplier's lead time.
+    pub lead_time_days: Option<f64>,
+    pub service_level: Option<f64>,
+    pub supplier: Option<String>,
+    /// Also list items that do not need ordering today.
+    pub include_all: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderSuggestion {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub current_stock: f64,
+    pub forecast_daily_demand: f64,
+    /// Part of the daily demand that comes from dishes using this product as an ingredient.
+    pub recipe_daily_demand: f64,
+    pub lead_time_days: f64,
+    pub lead_time_demand: f64,
+    pub safety_stock: f64,
+    pub reorder_point: f64,
+    /// Restock level set in the POS, for comparison with the computed reorder point.
+    pub pos_restock_level: Option<f64>,
+    pub order_today: bool,
+    pub suggested_quantity: f64,
+    pub order_cost: Option<f64>,
+    pub shelf_life_days: Option<f64>,
+    /// Units of a delivery arriving after the lead time that can be sold
+    /// before it expires, after the stock still left by then. `None` when
+    /// the expiry is past the forecast horizon.
+    pub sellable_before_expiry: Option<f64>,
+    /// Whether the suggested quantity was cut down to what sells before expiry.
+    pub capped_by_expiry: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupplierOrder {
+    pub supplier: String,
+    pub items: Vec<OrderSuggestion>,
+    pub total_cost: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplenishmentReport {
+    /// Last day of sales history the forecasts start from.
+    pub history_end: Option<NaiveDate>,
+    pub service_level: f64,
+    pub suppliers: Vec<SupplierOrder>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StockRow {
+    product_id: i32,
+    name: Option<String>,
+    unit: Option<String>,
+    supplier: Option<String>,
+    current_stock: Option<f32>,
+    restock_level: Option<f32>,
+    restock_quantity: Option<f32>,
+    cost_price: Option<f32>,
+    shelf_life_days: Option<f32>,
+}
+
+/// Forecast demand of one product from the day after the history, its own
+/// sales plus what the dishes made from it use.
+#[derive(Default)]
+struct Demand {
+    daily: Vec<f64>,
+    residual_sd: f64,
+    recipe_daily: Vec<f64>,
+}
+
+/// What to order today, per supplier: products whose stock is at or below a
+/// reorder point of expected lead-time demand plus safety stock, topped up to
+/// cover the review period as well.
+pub async fn get_replenishment(
+    pool: &PgPool,
+    config: &ReplenishmentConfig,
+    options: &ReplenishmentOptions,
+) -> Result<ReplenishmentReport> {
+    if options
+        .lead_time_days
+        .is_some_and(|days| !days.is_finite() || days > MAX_PLANNING_DAYS)
+    {
+        bail!(RequestError::Invalid(format!(
+            "lead_time_days must be a number of at most {} days",
+            MAX_PLANNING_DAYS
+        )));
+    }
+    if options.service_level.is_some_and(|level| !level.is_finite()) {
+        bail!(RequestError::Invalid("service_level must be a number".to_string()));
+    }
+    let service_level = options
+        .service_level
+        .unwrap_or(config.service_level)
+        .clamp(0.5, 0.999);
+    let z = normal_quantile(service_level);
+    let lead_time_for = |product: &StockRow| {
+        options
+            .lead_time_days
+            .unwrap_or_else(|| config.lead_time_for(supplier_name(product.supplier.as_deref())))
+            .max(0.0)
+    };
+    let products = sqlx::query_as::<_, StockRow>(
+        "SELECT product_id, name, unit, supplier, current_stock, restock_level, restock_quantity, cost_price,
+                shelf_life_days
+         FROM products",
+    )
+    .fetch_all(pool)
+    .await?;
+    let usage = recipes::usage_per_portion(&recipes::load_bom(pool).await?);
+
+    let horizons = forecast_horizons(&products, &usage, lead_time_for, config.review_days);
+
+    let series = forecasting::get_demand_series(pool, None, None).await?;
+    let selected = forecast_accuracy::get_selected_methods(pool).await?;
+    let calendar = calendar::load_calendar(pool).await?;
+    let history_end = series.values().map(|s| s.end()).max();
+    let forecast_horizons = horizons.clone();
+    let mut demand: HashMap<i32, Demand> = forecasting::fit_blocking(move || {
+        series
+            .values()
+            .filter_map(|series| {
+                let horizon = *forecast_horizons.get(&series.product_id)?;
+                let forecast_options = ForecastOptions {
+                    horizon: Some(horizon),
+                    ..Default::default()
+                };
+                let forecast = forecasting::forecast_series(series, &forecast_options, &selected, &calendar);
+                let demand = Demand {
+                    daily: forecast.daily.iter().map(|d| d.units).collect(),
+                    residual_sd: forecast.residual_sd,
+                    recipe_daily: vec![0.0; horizon],
+                };
+                Some((series.product_id, demand))
+            })
+            .collect()
+    })
+    .await?;
+
+    // Dishes are made to order, so their forecasts are bought as ingredients;
+    // dish errors add to the ingredient's as independent variances.
+    for (dish, ingredients) in &usage {
+        let Some(dish_demand) = demand.get(dish) else {
+            continue;
+        };
+        let (dish_daily, dish_sd) = (dish_demand.daily.clone(), dish_demand.residual_sd);
+        for (ingredient, per_portion) in ingredients {
+            let horizon = horizons.get(ingredient).copied().unwrap_or(dish_daily.len());
+            let entry = demand.entry(*ingredient).or_insert_with(|| Demand {
+                daily: vec![0.0; horizon],
+                recipe_daily: vec![0.0; horizon],
+                ..Default::default()
+            });
+            for ((total, from_recipes), units) in
+                entry.daily.iter_mut().zip(entry.recipe_daily.iter_mut()).zip(&dish_daily)
+            {
+                *total += units * per_portion;
+                *from_recipes += units * per_portion;
+            }
+            entry.residual_sd = entry.residual_sd.hypot(dish_sd * per_portion);
+        }
+    }
+
+    let mut suppliers: BTreeMap<String, Vec<OrderSuggestion>> = BTreeMap::new();
+    for product in products {
+        if usage.contains_key(&product.product_id) {
+            continue;
+        }
+        let Some(demand) = demand.get(&product.product_id) else {
+            continue;
+        };
+        let supplier = supplier_name(product.supplier.as_deref()).to_string();
+        if options.supplier.as_ref().is_some_and(|s| !s.eq_ignore_ascii_case(&supplier)) {
+            continue;
+        }
+
+        let suggestion = suggest(&product, demand, lead_time_for(&product), config.review_days, z);
+        if suggestion.order_today || options.include_all.unwrap_or(false) {
+            suppliers.entry(supplier).or_default().push(suggestion);
+        }
+    }
+
+    let suppliers = suppliers
+        .into_iter()
+        .map(|(supplier, mut items)| {
+            items.sort_by_key(|item| item.product_id);
+            let total_cost = items.iter().filter_map(|item| item.order_cost).sum();
+            SupplierOrder {
+                supplier,
+                items,
+                total_cost,
+            }
+        })
+        .collect();
+
+    Ok(ReplenishmentReport {
+        history_end,
+        service_level,
+        suppliers,
+    })
+}
+
+// Each product is forecast far enough to cover its lead time and the review
+// period and, for perishables, the shelf life of a delivery, up to the longest
+// horizon forecasts allow. Dishes cover the longest horizon of their
+// ingredients.
+fn forecast_horizons(
+    products: &[StockRow],
+    usage: &HashMap<i32, HashMap<i32, f64>>,
+    lead_time_for: impl Fn(&StockRow) -> f64,
+    review_days: f64,
+) -> HashMap<i32, usize> {
+    let mut horizons: HashMap<i32, usize> = products
+        .iter()
+        .map(|product| {
+            let shelf_life = product.shelf_life_days.map_or(0.0, |days| days as f64);
+            let days = lead_time_for(product) + review_days.max(shelf_life);
+            let horizon = (days.ceil() as usize).saturating_add(1).min(forecasting::MAX_HORIZON);
+            (product.product_id, horizon)
+        })
+        .collect();
+    for (dish, ingredients) in usage {
+        let needed = ingredients
+            .keys()
+            .filter_map(|ingredient| horizons.get(ingredient))
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let horizon = horizons.entry(*dish).or_insert(0);
+        *horizon = (*horizon).max(needed);
+    }
+    horizons
+}
+
+// Lead-time demand is the forecast summed over the lead time, so weekend
+// peaks inside it count; its uncertainty grows with the square root of the
+// lead time, assuming independent daily errors. The POS restock quantity is
+// treated as the minimum order, but a perishable order never exceeds what is
+// forecast to sell between its arrival and its expiry, since the rest would
+// be thrown away. When the expiry is past the forecast horizon, what sells
+// before it is unknown and the order is not capped.
+fn suggest(
+    product: &StockRow,
+    demand: &Demand,
+    lead_time_days: f64,
+    review_days: f64,
+    z: f64,
+) -> OrderSuggestion {
+    let current_stock = product.current_stock.unwrap_or(0.0).max(0.0) as f64;
+    let lead_time_demand = demand_over(&demand.daily, 0.0, lead_time_days);
+    let review_demand = demand_over(&demand.daily, lead_time_days, review_days);
+    let safety_stock = z * demand.residual_sd * lead_time_days.sqrt();
+    let reorder_point = lead_time_demand + safety_stock;
+
+    let order_today = current_stock <= reorder_point;
+    let mut suggested_quantity = if order_today {
+        let needed = (reorder_point + review_demand - current_stock).max(0.0).ceil();
+        let minimum = product.restock_quantity.unwrap_or(0.0).max(0.0) as f64;
+        needed.max(minimum)
+    } else {
+        0.0
+    };
+
+    let shelf_life_days = product
+        .shelf_life_days
+        .filter(|days| *days > 0.0)
+        .map(|days| days as f64);
+    let sellable_before_expiry = shelf_life_days
+        .filter(|shelf_life| lead_time_days + shelf_life <= demand.daily.len() as f64)
+        .map(|shelf_life| {
+            let left_at_arrival = (current_stock - lead_time_demand).max(0.0);
+            (demand_over(&demand.daily, lead_time_days, shelf_life) - left_at_arrival)
+                .max(0.0)
+                .floor()
+        });
+    let capped_by_expiry = sellable_before_expiry.is_some_and(|cap| suggested_quantity > cap);
+    if let Some(cap) = sellable_before_expiry.filter(|_| capped_by_expiry) {
+        suggested_quantity = cap;
+    }
+
+    OrderSuggestion {
+        product_id: product.product_id,
+        name: product.name.clone(),
+        unit: product.unit.clone(),
+        current_stock,
+        forecast_daily_demand: demand.daily.first().copied().unwrap_or(0.0),
+        recipe_daily_demand: demand.recipe_daily.first().copied().unwrap_or(0.0),
+        lead_time_days,
+        lead_time_demand,
+        safety_stock,
+        reorder_point,
+        pos_restock_level: product.restock_level.map(|level| level as f64),
+        order_today,
+        suggested_quantity,
+        order_cost: product
+            .cost_price
+            .filter(|cost| *cost > 0.0)
+            .map(|cost| cost as f64 * suggested_quantity),
+        shelf_life_days,
+        sellable_before_expiry,
+        capped_by_expiry,
+    }
+}
+
+/// Forecast units between `from` and `from + days` days ahead, counting
+/// partial days pro rata.
+fn demand_over(daily: &[f64], from: f64, days: f64) -> f64 {
+    let to = from + days;
+    daily
+        .iter()
+        .enumerate()
+        .map(|(day, units)| {
+            let overlap = (to.min(day as f64 + 1.0) - from.max(day as f64)).max(0.0);
+            units * overlap
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(product_id: i32, current_stock: f32, shelf_life_days: Option<f32>) -> StockRow {
+        StockRow {
+            product_id,
+            name: None,
+            unit: None,
+            supplier: None,
+            current_stock: Some(current_stock),
+            restock_level: None,
+            restock_quantity: None,
+            cost_price: None,
+            shelf_life_days,
+        }
+    }
+
+    fn demand(daily: Vec<f64>, residual_sd: f64) -> Demand {
+        Demand {
+            recipe_daily: vec![0.0; daily.len()],
+            daily,
+            residual_sd,
+        }
+    }
+
+    #[test]
+    fn demand_over_counts_partial_days() {
+        let daily = [2.0, 4.0, 6.0];
+        assert!((demand_over(&daily, 0.0, 1.5) - 4.0).abs() < 1e-9);
+        assert!((demand_over(&daily, 1.5, 1.0) - 5.0).abs() < 1e-9);
+        // Nothing is forecast past the horizon.
+        assert!((demand_over(&daily, 2.5, 5.0) - 3.0).abs() < 1e-9);
+        assert_eq!(demand_over(&daily, 1.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn fractional_lead_time_sets_reorder_point_and_quantity() {
+        let daily = demand(vec![10.0; 10], 2.0);
+        let suggestion = suggest(&product(1, 5.0, None), &daily, 1.5, 2.0, 1.0);
+        let safety_stock = 2.0 * 1.5f64.sqrt();
+        assert!((suggestion.lead_time_demand - 15.0).abs() < 1e-9);
+        assert!((suggestion.safety_stock - safety_stock).abs() < 1e-9);
+        assert!((suggestion.reorder_point - (15.0 + safety_stock)).abs() < 1e-9);
+        assert!(suggestion.order_today);
+        // Reorder point plus two days of review demand, less what is in stock.
+        assert_eq!(suggestion.suggested_quantity, 33.0);
+
+        let stocked = suggest(&product(1, 20.0, None), &daily, 1.5, 2.0, 1.0);
+        assert!(!stocked.order_today);
+        assert_eq!(stocked.suggested_quantity, 0.0);
+    }
+
+    #[test]
+    fn perishable_orders_are_capped_at_what_sells_before_expiry() {
+        let mut milk = product(1, 0.0, Some(2.0));
+        milk.restock_quantity = Some(60.0);
+        let daily = demand(vec![10.0; 5], 0.0);
+        let suggestion = suggest(&milk, &daily, 1.0, 7.0, 1.0);
+        // Arrives after a day and keeps for two: 20 units sell before expiry.
+        assert_eq!(suggestion.sellable_before_expiry, Some(20.0));
+        assert!(suggestion.capped_by_expiry);
+        assert_eq!(suggestion.suggested_quantity, 20.0);
+
+        // Expiry past the forecast horizon leaves the order uncapped.
+        let long_life = suggest(&product(1, 0.0, Some(10.0)), &daily, 1.0, 7.0, 1.0);
+        assert_eq!(long_life.sellable_before_expiry, None);
+        assert!(!long_life.capped_by_expiry);
+        assert_eq!(long_life.suggested_quantity, 50.0);
+    }
+
+    #[test]
+    fn dishes_take_the_longest_horizon_of_their_ingredients() {
+        let products = [
+            product(1, 0.0, Some(10.0)),
+            product(2, 0.0, None),
+            product(3, 0.0, None),
+            product(5, 0.0, None),
+        ];
+        let usage = HashMap::from([
+            (3, HashMap::from([(1, 0.2), (2, 1.0)])),
+            (4, HashMap::from([(2, 0.5)])),
+        ]);
+        let lead_time_for = |product: &StockRow| match product.product_id {
+            3 => 0.0,
+            5 => 1e9,
+            _ => 2.0,
+        };
+        let horizons = forecast_horizons(&products, &usage, lead_time_for, 3.0);
+
+        // Two days' lead time plus the longer of review period and shelf life.
+        assert_eq!(horizons[&1], 13);
+        assert_eq!(horizons[&2], 6);
+        assert_eq!(horizons[&3], 13);
+        // A dish missing from the products still gets its ingredients' horizon.
+        assert_eq!(horizons[&4], 6);
+        assert_eq!(horizons[&5], forecasting::MAX_HORIZON);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::forecasting;
+use crate::recipes;
+use crate::replenishment::{self, ReplenishmentConfig};
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+
+const STOCK_OUT_RISK: &str = "stock_out_risk";
+const HISTORICAL_STOCK_OUT: &str = "historical_stock_out";
+// Days of stored forecast averaged into the daily demand.
+const DEMAND_DAYS: i64 = 7;
+// A zero-sale day only counts as a stock-out for an item that sold at least
+// this much per day, on nearly every open day of the weeks before.
+const BASELINE_DAYS: usize = 28;
+const MIN_BUSY_UNITS: f64 = 3.0;
+const MIN_SELLING_SHARE: f64 = 0.9;
+const HISTORY_LOOKBACK_DAYS: i64 = 90;
+
+#[derive(Debug, Deserialize)]
+pub struct AlertQuery {
+    pub alert_type: Option<String>,
+    pub product_id: Option<i32>,
+    pub include_resolved: Option<bool>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockAlert {
+    pub id: i32,
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub alert_type: String,
+    pub severity: String,
+    pub message: String,
+    pub current_stock: Option<f64>,
+    pub daily_demand: Option<f64>,
+    pub days_of_cover: Option<f64>,
+    pub lead_time_days: Option<f64>,
+    pub alert_date: Option<NaiveDate>,
+    pub raised_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct AlertSummary {
+    pub at_risk: usize,
+    pub resolved: u64,
+    pub historical_stock_outs: u64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ProductStock {
+    product_id: i32,
+    name: Option<String>,
+    supplier: Option<String>,
+    current_stock: Option<f32>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StoredDemand {
+    product_id: i32,
+    daily_demand: f64,
+}
+
+struct Risk {
+    product_id: i32,
+    severity: &'static str,
+    message: String,
+    current_stock: f64,
+    daily_demand: f64,
+    days_of_cover: f64,
+    lead_time_days: f64,
+}
+
+/// Refreshes the alerts table from the stored forecasts: raises or updates a
+/// risk alert for every product whose stock will not last until an order
+/// placed today arrives, resolves the ones that no longer apply and records
+/// past days that look like stock-outs.
+pub async fn raise_stock_alerts(pool: &PgPool, config: &ReplenishmentConfig) -> Result<AlertSummary> {
+    let risks = find_risks(pool, config).await?;
+    let stock_outs = find_historical_stock_outs(pool).await?;
+
+    let mut summary = AlertSummary {
+        at_risk: risks.len(),
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+    for risk in &risks {
+        let updated = sqlx::query(
+            "UPDATE stock_alerts
+             SET severity = $2, message = $3, current_stock = $4, daily_demand = $5,
+                 days_of_cover = $6, lead_time_days = $7, updated_at = CURRENT_TIMESTAMP
+             WHERE product_id = $1 AND alert_type = 'stock_out_risk' AND resolved_at IS NULL",
+        )
+        .bind(risk.product_id)
+        .bind(risk.severity)
+        .bind(&risk.message)
+        .bind(risk.current_stock)
+        .bind(risk.daily_demand)
+        .bind(risk.days_of_cover)
+        .bind(risk.lead_time_days)
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            sqlx::query(
+                "INSERT INTO stock_alerts
+                    (product_id, alert_type, severity, message, current_stock, daily_demand,
+                     days_of_cover, lead_time_days)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(risk.product_id)
+            .bind(STOCK_OUT_RISK)
+            .bind(risk.severity)
+            .bind(&risk.message)
+            .bind(risk.current_stock)
+            .bind(risk.daily_demand)
+            .bind(risk.days_of_cover)
+            .bind(risk.lead_time_days)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    let at_risk: Vec<i32> = risks.iter().map(|r| r.product_id).collect();
+    summary.resolved = sqlx::query(
+        "UPDATE stock_alerts SET resolved_at = CURRENT_TIMESTAMP
+         WHERE alert_type = 'stock_out_risk' AND resolved_at IS NULL AND product_id <> ALL($1)",
+    )
+    .bind(&at_risk)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    for (product_id, date, usual) in &stock_outs {
+        summary.historical_stock_outs += sqlx::query(
+            "INSERT INTO stock_alerts (product_id, alert_type, severity, message, daily_demand, alert_date)
+             VALUES ($1, $2, 'info', $3, $4, $5)
+             ON CONFLICT (product_id, alert_type, alert_date) WHERE alert_date IS NOT NULL DO NOTHING",
+        )
+        .bind(product_id)
+        .bind(HISTORICAL_STOCK_OUT)
+        .bind(format!(
+            "No sales on {} although it usually sells {:.1} a day; likely out of stock.",
+            date, usual
+        ))
+        .bind(usual)
+        .bind(date)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    tx.commit().await?;
+
+    Ok(summary)
+}
+
+pub async fn get_stock_alerts(pool: &PgPool, query: &AlertQuery) -> Result<Vec<StockAlert>, sqlx::Error> {
+    sqlx::query_as::<_, StockAlert>(
+        r#"
+        SELECT a.id, a.product_id, p.name, a.alert_type, a.severity, a.message, a.current_stock,
+               a.daily_demand, a.days_of_cover, a.lead_time_days, a.alert_date, a.raised_at,
+               a.updated_at, a.resolved_at
+        FROM stock_alerts a
+        JOIN products p ON p.product_id = a.product_id
+        WHERE ($1::VARCHAR IS NULL OR a.alert_type = $1)
+          AND ($2::INTEGER IS NULL OR a.product_id = $2)
+          AND ($3 OR a.resolved_at IS NULL)
+        ORDER BY CASE a.severity WHEN 'critical' THEN 0 WHEN 'warning' THEN 1 ELSE 2 END,
+                 a.days_of_cover NULLS LAST, a.alert_date DESC, a.product_id
+        "#,
+    )
+    .bind(&query.alert_type)
+    .bind(query.product_id)
+    .bind(query.include_resolved.unwrap_or(false))
+    .fetch_all(pool)
+    .await
+}
+
+// Days of cover use the average stored forecast over the next week, plus what
+// dishes made from the product will use. Critical when stock runs out before
+// an order placed today can arrive, a warning when it runs out before the
+// following order would.
+async fn find_risks(pool: &PgPool, config: &ReplenishmentConfig) -> Result<Vec<Risk>> {
+    let stored = sqlx::query_as::<_, StoredDemand>(
+        "SELECT product_id, AVG(units) AS daily_demand
+         FROM demand_forecasts
+         WHERE forecast_date < (SELECT MIN(forecast_date) FROM demand_forecasts) + $1::INTEGER
+         GROUP BY product_id",
+    )
+    .bind(DEMAND_DAYS as i32)
+    .fetch_all(pool)
+    .await?;
+    let mut demand: HashMap<i32, f64> =
+        stored.iter().map(|row| (row.product_id, row.daily_demand)).collect();
+
+    let usage = recipes::usage_per_portion(&recipes::load_bom(pool).await?);
+    for (dish, ingredients) in &usage {
+        let Some(portions) = demand.get(dish).copied() else {
+            continue;
+        };
+        for (ingredient, per_portion) in ingredients {
+            *demand.entry(*ingredient).or_insert(0.0) += portions * per_portion;
+        }
+    }
+
+    let products = sqlx::query_as::<_, ProductStock>(
+        "SELECT product_id, name, supplier, current_stock FROM products",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut risks = Vec::new();
+    for product in products {
+        // Dishes are made to order; their ingredients carry the risk.
+        if usage.contains_key(&product.product_id) {
+            continue;
+        }
+        let Some(daily_demand) = demand.get(&product.product_id).copied().filter(|d| *d > 0.0) else {
+            continue;
+        };
+        let supplier = replenishment::supplier_name(product.supplier.as_deref());
+        let lead_time_days = config.lead_time_for(supplier);
+        let current_stock = product.current_stock.unwrap_or(0.0).max(0.0) as f64;
+        let days_of_cover = current_stock / daily_demand;
+
+        let severity = if days_of_cover < lead_time_days {
+            "critical"
+        } else if days_of_cover < lead_time_days + config.review_days {
+            "warning"
+        } else {
+            continue;
+        };
+        risks.push(Risk {
+            product_id: product.product_id,
+            severity,
+            message: format!(
+                "{} has {:.1} days of cover ({:.1} in stock, {:.1} a day expected); deliveries take {:.1} days.",
+                product.name.as_deref().unwrap_or("Product"),
+                days_of_cover,
+                current_stock,
+                daily_demand,
+                lead_time_days
+            ),
+            current_stock,
+            daily_demand,
+            days_of_cover,
+            lead_time_days,
+        });
+    }
+    Ok(risks)
+}
+
+/// Days the store was trading but a normally busy item sold nothing.
+async fn find_historical_stock_outs(pool: &PgPool) -> Result<Vec<(i32, NaiveDate, f64)>> {
+    let history_days = HISTORY_LOOKBACK_DAYS + BASELINE_DAYS as i64;
+    let series = forecasting::get_demand_series(pool, Some(history_days), None).await?;
+    let Some(end) = series.values().map(|s| s.end()).max() else {
+        return Ok(Vec::new());
+    };
+    let open_days: HashSet<NaiveDate> = sqlx::query_scalar::<_, NaiveDate>(
+        "SELECT DISTINCT transaction_date::DATE FROM receipts
+         WHERE transaction_date IS NOT NULL AND transaction_date::DATE > $1",
+    )
+    .bind(end - Duration::days(history_days))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+    let lookback_start = end - Duration::days(HISTORY_LOOKBACK_DAYS);
+
+    let mut stock_outs = Vec::new();
+    for series in series.values() {
+        for t in BASELINE_DAYS..series.daily.len() {
+            let date = series.start + Duration::days(t as i64);
+            if series.daily[t] > 0.0 || date <= lookback_start || !open_days.contains(&date) {
+                continue;
+            }
+            let baseline: Vec<f64> = (t - BASELINE_DAYS..t)
+                .filter(|d| open_days.contains(&(series.start + Duration::days(*d as i64))))
+                .map(|d| series.daily[d])
+                .collect();
+            if baseline.is_empty() {
+                continue;
+            }
+            let usual = baseline.iter().sum::<f64>() / baseline.len() as f64;
+            let selling = baseline.iter().filter(|units| **units > 0.0).count() as f64;
+            if usual >= MIN_BUSY_UNITS && selling / baseline.len() as f64 >= MIN_SELLING_SHARE {
+                stock_outs.push((series.product_id, date, usual));
+            }
+        }
+    }
+    Ok(stock_outs)
+}
@@ -0,0 +1,404 @@
+use crate::errors::RequestError;
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+
+// Stock differences smaller than this are float noise from the POS strings.
+const EPSILON: f64 = 1e-6;
+
+/// Inventory fields of one product as the POS last reported them.
+#[derive(Debug, Clone)]
+pub struct StockSnapshot {
+    pub product_id: i32,
+    pub current_stock: f64,
+    /// Running total used since the POS last reset it.
+    pub used_stock: f64,
+    /// Running total received since the POS last reset it.
+    pub additional_stock: f64,
+    pub shift_stock_fed: f64,
+    /// Stock counted at the end of the current shift.
+    pub shift_current_stock: f64,
+    pub adjusted_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockMovement {
+    pub id: i64,
+    pub product_id: i32,
+    pub movement_type: String,
+    pub quantity: f64,
+    pub stock_after: f64,
+    pub used_stock: Option<f64>,
+    pub additional_stock: Option<f64>,
+    pub shift_stock_fed: Option<f64>,
+    pub shift_current_stock: Option<f64>,
+    pub adjusted_at: Option<NaiveDateTime>,
+    pub occurred_at: NaiveDateTime,
+    pub recorded_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub movement_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LevelQuery {
+    /// Defaults to now.
+    pub at: Option<NaiveDateTime>,
+    pub product_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockLevel {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub stock: f64,
+    /// When the stock last changed before the requested time.
+    pub as_of: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VarianceQuery {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub product_id: Option<i32>,
+    /// Also list products whose stock did not move.
+    pub include_all: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StockVariance {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub opening_stock: f64,
+    pub received: f64,
+    pub used: f64,
+    /// Opening stock plus received minus used.
+    pub expected_stock: f64,
+    pub actual_stock: f64,
+    /// Actual minus expected; negative is shrinkage.
+    pub variance: f64,
+    /// Variance as a share of the units used, in percent.
+    pub variance_pct: Option<f64>,
+    pub variance_cost: Option<f64>,
+    pub adjustments: i64,
+    /// Shift closing counts minus the stock on the books at the time.
+    pub shift_count_variance: f64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct LastMovement {
+    id: i64,
+    stock_after: f64,
+    used_stock: Option<f64>,
+    additional_stock: Option<f64>,
+    shift_stock_fed: Option<f64>,
+    shift_current_stock: Option<f64>,
+    adjusted_at: Option<NaiveDateTime>,
+    occurred_at: NaiveDateTime,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct VarianceRow {
+    product_id: i32,
+    name: Option<String>,
+    unit: Option<String>,
+    cost_price: Option<f32>,
+    opening_stock: f64,
+    received: f64,
+    used: f64,
+    actual_stock: f64,
+    adjustments: i64,
+    shift_count_variance: f64,
+}
+
+/// Appends the movements that explain how `snapshot` differs from the last
+/// one recorded for the product: increases of the received and used counters
+/// (a counter that went down was reset, so its new value is the increase),
+/// then an adjustment for whatever stock change they do not account for, and
+/// a shift count when the shift fields changed. The first snapshot of a
+/// product is its opening balance. Movements are stamped with the POS update
+/// time, but never earlier than the product's last movement, so the ledger
+/// reads in the same order by time as it was written. Returns the number of
+/// rows written.
+pub async fn record_snapshot(pool: &PgPool, snapshot: &StockSnapshot) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // Snapshots of the same product are diffed one at a time; the product row
+    // is locked rather than the last movement so a product's first snapshots
+    // are serialized too.
+    sqlx::query("SELECT 1 FROM products WHERE product_id = $1 FOR UPDATE")
+        .bind(snapshot.product_id)
+        .execute(&mut *tx)
+        .await?;
+    let previous = sqlx::query_as::<_, LastMovement>(
+        "SELECT id, stock_after, used_stock, additional_stock, shift_stock_fed, shift_current_stock,
+                adjusted_at, occurred_at
+         FROM stock_movements
+         WHERE product_id = $1
+         ORDER BY occurred_at DESC, id DESC
+         LIMIT 1
+         FOR UPDATE",
+    )
+    .bind(snapshot.product_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let reported_at = snapshot
+        .updated_at
+        .unwrap_or_else(|| chrono::Local::now().naive_local());
+    let occurred_at = previous
+        .as_ref()
+        .map_or(reported_at, |previous| previous.occurred_at.max(reported_at));
+
+    let mut movements: Vec<(&str, f64, f64)> = Vec::new();
+    match previous {
+        None => movements.push(("opening", snapshot.current_stock, snapshot.current_stock)),
+        // Opening balances seeded from the products table carry no POS
+        // fields; this snapshot's become their baseline, and only a change in
+        // stock since the seed is recorded.
+        Some(previous) if previous.used_stock.is_none() => {
+            seed_baseline(&mut tx, previous.id, snapshot).await?;
+            let unexplained = snapshot.current_stock - previous.stock_after;
+            if unexplained.abs() > EPSILON {
+                movements.push(("adjustment", unexplained, snapshot.current_stock));
+            }
+        }
+        Some(previous) => {
+            let received = counter_increase(previous.additional_stock, snapshot.additional_stock);
+            let used = counter_increase(previous.used_stock, snapshot.used_stock);
+            let mut stock = previous.stock_after;
+            if received > EPSILON {
+                stock += received;
+                movements.push(("received", received, stock));
+            }
+            if used > EPSILON {
+                stock -= used;
+                movements.push(("used", -used, stock));
+            }
+            let unexplained = snapshot.current_stock - stock;
+            if unexplained.abs() > EPSILON || snapshot.adjusted_at != previous.adjusted_at {
+                movements.push(("adjustment", unexplained, snapshot.current_stock));
+            }
+            let shift_changed = |old: Option<f64>, new: f64| old.is_none_or(|old| (old - new).abs() > EPSILON);
+            if shift_changed(previous.shift_stock_fed, snapshot.shift_stock_fed)
+                || shift_changed(previous.shift_current_stock, snapshot.shift_current_stock)
+            {
+                movements.push(("shift_count", 0.0, snapshot.current_stock));
+            }
+        }
+    }
+    for (movement_type, quantity, stock_after) in &movements {
+        sqlx::query(
+            r#"
+            INSERT INTO stock_movements
+                (product_id, movement_type, quantity, stock_after, used_stock, additional_stock,
+                 shift_stock_fed, shift_current_stock, adjusted_at, occurred_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .bind(snapshot.product_id)
+        .bind(movement_type)
+        .bind(quantity)
+        .bind(stock_after)
+        .bind(snapshot.used_stock)
+        .bind(snapshot.additional_stock)
+        .bind(snapshot.shift_stock_fed)
+        .bind(snapshot.shift_current_stock)
+        .bind(snapshot.adjusted_at)
+        .bind(occurred_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(movements.len())
+}
+
+async fn seed_baseline(
+    conn: &mut PgConnection,
+    id: i64,
+    snapshot: &StockSnapshot,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE stock_movements
+         SET used_stock = $2, additional_stock = $3, shift_stock_fed = $4, shift_current_stock = $5,
+             adjusted_at = $6
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(snapshot.used_stock)
+    .bind(snapshot.additional_stock)
+    .bind(snapshot.shift_stock_fed)
+    .bind(snapshot.shift_current_stock)
+    .bind(snapshot.adjusted_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn counter_increase(previous: Option<f64>, current: f64) -> f64 {
+    match previous {
+        Some(previous) if current >= previous => current - previous,
+        Some(_) => current,
+        None => 0.0,
+    }
+}
+
+pub async fn get_stock_history(
+    pool: &PgPool,
+    product_id: i32,
+    query: &HistoryQuery,
+) -> Result<Vec<StockMovement>, sqlx::Error> {
+    sqlx::query_as::<_, StockMovement>(
+        r#"
+        SELECT id, product_id, movement_type, quantity, stock_after, used_stock, additional_stock,
+               shift_stock_fed, shift_current_stock, adjusted_at, occurred_at, recorded_at
+        FROM stock_movements
+        WHERE product_id = $1
+          AND ($2::TIMESTAMP IS NULL OR occurred_at >= $2)
+          AND ($3::TIMESTAMP IS NULL OR occurred_at <= $3)
+          AND ($4::VARCHAR IS NULL OR movement_type = $4)
+        ORDER BY occurred_at, id
+        "#,
+    )
+    .bind(product_id)
+    .bind(query.from)
+    .bind(query.to)
+    .bind(&query.movement_type)
+    .fetch_all(pool)
+    .await
+}
+
+/// Stock on hand per product at a point in time, from the last movement at
+/// or before it. Products not yet in the ledger then are left out.
+pub async fn get_stock_levels(pool: &PgPool, query: &LevelQuery) -> Result<Vec<StockLevel>, sqlx::Error> {
+    let at = query.at.unwrap_or_else(|| chrono::Local::now().naive_local());
+    sqlx::query_as::<_, StockLevel>(
+        r#"
+        SELECT m.product_id, p.name, p.unit, m.stock_after AS stock, m.occurred_at AS as_of
+        FROM (
+            SELECT DISTINCT ON (product_id) product_id, stock_after, occurred_at
+            FROM stock_movements
+            WHERE occurred_at <= $1 AND ($2::INTEGER IS NULL OR product_id = $2)
+            ORDER BY product_id, occurred_at DESC, id DESC
+        ) m
+        JOIN products p ON p.product_id = m.product_id
+        ORDER BY m.product_id
+        "#,
+    )
+    .bind(at)
+    .bind(query.product_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Expected against actual stock per product over a period, 30 days up to
+/// now by default. Expected stock is the opening balance plus what was
+/// received minus what was used; the difference from the stock on hand at the
+/// end is what adjustments and counts corrected, i.e. shrinkage when negative.
+pub async fn get_stock_variance(pool: &PgPool, query: &VarianceQuery) -> Result<Vec<StockVariance>> {
+    let to = query.to.unwrap_or_else(|| chrono::Local::now().naive_local());
+    let from = match query.from {
+        Some(from) => from,
+        None => to.checked_sub_signed(Duration::days(30)).ok_or_else(|| {
+            RequestError::Invalid("to is too early to default from; pass from as well".to_string())
+        })?,
+    };
+    if to < from {
+        bail!(RequestError::Invalid("to must not be before from".to_string()));
+    }
+
+    let rows = sqlx::query_as::<_, VarianceRow>(
+        r#"
+        WITH opening AS (
+            SELECT DISTINCT ON (product_id) product_id, stock_after
+            FROM stock_movements
+            WHERE occurred_at < $1
+            ORDER BY product_id, occurred_at DESC, id DESC
+        ),
+        closing AS (
+            SELECT DISTINCT ON (product_id) product_id, stock_after
+            FROM stock_movements
+            WHERE occurred_at <= $2
+            ORDER BY product_id, occurred_at DESC, id DESC
+        ),
+        period AS (
+            SELECT product_id,
+                   COALESCE(SUM(quantity) FILTER (WHERE movement_type = 'opening'), 0) AS opened,
+                   COALESCE(SUM(quantity) FILTER (WHERE movement_type = 'received'), 0) AS received,
+                   COALESCE(-SUM(quantity) FILTER (WHERE movement_type = 'used'), 0) AS used,
+                   COUNT(*) FILTER (WHERE movement_type = 'adjustment') AS adjustments,
+                   COALESCE(SUM(shift_current_stock - stock_after)
+                            FILTER (WHERE movement_type = 'shift_count'), 0) AS shift_count_variance
+            FROM stock_movements
+            WHERE occurred_at >= $1 AND occurred_at <= $2
+            GROUP BY product_id
+        )
+        SELECT c.product_id, p.name, p.unit, p.cost_price,
+               COALESCE(o.stock_after, 0) + COALESCE(r.opened, 0) AS opening_stock,
+               COALESCE(r.received, 0) AS received,
+               COALESCE(r.used, 0) AS used,
+               c.stock_after AS actual_stock,
+               COALESCE(r.adjustments, 0) AS adjustments,
+               COALESCE(r.shift_count_variance, 0) AS shift_count_variance
+        FROM closing c
+        JOIN products p ON p.product_id = c.product_id
+        LEFT JOIN opening o ON o.product_id = c.product_id
+        LEFT JOIN period r ON r.product_id = c.product_id
+        WHERE $3::INTEGER IS NULL OR c.product_id = $3
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(query.product_id)
+    .fetch_all(pool)
+    .await?;
+
+    let include_all = query.include_all.unwrap_or(false);
+    let mut variances: Vec<StockVariance> = rows
+        .into_iter()
+        .map(|row| {
+            let expected_stock = row.opening_stock + row.received - row.used;
+            let variance = row.actual_stock - expected_stock;
+            StockVariance {
+                product_id: row.product_id,
+                name: row.name,
+                unit: row.unit,
+                opening_stock: row.opening_stock,
+                received: row.received,
+                used: row.used,
+                expected_stock,
+                actual_stock: row.actual_stock,
+                variance,
+                variance_pct: (row.used > 0.0).then(|| 100.0 * variance / row.used),
+                variance_cost: row
+                    .cost_price
+                    .filter(|cost| *cost > 0.0)
+                    .map(|cost| cost as f64 * variance),
+                adjustments: row.adjustments,
+                shift_count_variance: row.shift_count_variance,
+            }
+        })
+        .filter(|v| {
+            include_all
+                || v.variance.abs() > EPSILON
+                || v.received > EPSILON
+                || v.used > EPSILON
+                || v.shift_count_variance.abs() > EPSILON
+        })
+        .collect();
+    // Costliest losses first.
+    variances.sort_by(|a, b| {
+        a.variance_cost
+            .unwrap_or(0.0)
+            .total_cmp(&b.variance_cost.unwrap_or(0.0))
+            .then(a.variance.total_cmp(&b.variance))
+    });
+    Ok(variances)
+}
@@ -1,7 +1,5 @@
-use chrono::NaiveDateTime;
-use linfa::prelude::*;
-use linfa_linear::LinearRegression;
-use ndarray::{Array1, Array2};
+use crate::forecast_accuracy::{self, AccuracyQuery, MethodAccuracy};
+use crate::forecasting::{self, ForecastRun};
 use serde::Serialize;
 use sqlx::PgPool;
 use std::collections::HashMap;
@@ -10,84 +8,81 @@ use std::collections::HashMap;
 pub struct StockOptimization {
     pub product_id: i32,
     pub product_name: String,
+    /// Forecast units for the day after the latest sale.
     pub predicted_demand: f64,
+    pub method: String,
+    /// Backtest accuracy of the method, once a backtest has selected it.
+    pub accuracy: Option<MethodAccuracy>,
 }
 
-pub async fn get_stock_optimization(
-    pool: &PgPool,
-) -> Result<Vec<StockOptimization>, sqlx::Error> {
-    let sales_data = get_sales_data(pool).await?;
-
-    let mut optimizations = Vec::new();
-
-    for (product_id, records) in &sales_data {
-        if records.len() < 2 {
-            continue; // Not enough data to make a prediction
-        }
-
-        let (timestamps, quantities): (Vec<f64>, Vec<f64>) = records
-            .iter()
-            .map(|(ts, q)| (ts.and_utc().timestamp() as f64, *q as f64))
-            .unzip();
-
-        let timestamps = Array2::from_shape_vec((timestamps.len(), 1), timestamps).unwrap();
-        let quantities = Array1::from_vec(quantities);
-
-        let dataset = Dataset::new(timestamps, quantities);
-
-        let lin_reg = LinearRegression::new();
-        let model = lin_reg.fit(&dataset).unwrap();
-
-        let last_timestamp = records.last().unwrap().0.and_utc().timestamp() as f64;
-        let next_timestamp = last_timestamp + (24.0 * 3600.0); // Predict for the next day
-
-        let next_timestamp_array = Array2::from_elem((1, 1), next_timestamp);
-        
-        let predicted_demand = model.predict(&next_timestamp_array).get(0).cloned().unwrap_or(0.0);
-
-        let product_name = get_product_name(pool, *product_id).await?.unwrap_or_default();
-
-        optimizations.push(StockOptimization {
-            product_id: *product_id,
-            product_name,
-            predicted_demand,
-        });
-    }
+#[derive(Serialize, Debug)]
+pub struct StockOptimizationReport {
+    /// The forecast run served; `None` until the background job has run once.
+    pub forecast_run: Option<ForecastRun>,
+    pub age_minutes: Option<i64>,
+    pub products: Vec<StockOptimization>,
+}
 
-    Ok(optimizations)
+#[derive(Debug, sqlx::FromRow)]
+struct StoredForecast {
+    product_id: i32,
+    name: Option<String>,
+    method: String,
+    units: f64,
 }
 
-async fn get_sales_data(
-    pool: &PgPool,
-) -> Result<HashMap<i32, Vec<(NaiveDateTime, f32)>>, sqlx::Error> {
-    let rows = sqlx::query!(
-        "SELECT p.product_id, s.quantity, r.transaction_date
-         FROM sales s
-         JOIN products p ON s.product_id = p.product_id
-         JOIN receipts r ON s.receipt_id = r.receipt_id
-         WHERE r.transaction_date IS NOT NULL AND s.quantity IS NOT NULL AND p.product_id IS NOT NULL"
+/// Serves the forecasts stored by the last background run rather than fitting
+/// anything per request.
+pub async fn get_stock_optimization(pool: &PgPool) -> Result<StockOptimizationReport, sqlx::Error> {
+    let Some((run, age_minutes)) = forecasting::get_latest_run(pool).await? else {
+        return Ok(StockOptimizationReport {
+            forecast_run: None,
+            age_minutes: None,
+            products: Vec::new(),
+        });
+    };
+
+    let rows = sqlx::query_as::<_, StoredForecast>(
+        r#"
+        SELECT f.product_id, p.name, f.method, f.units
+        FROM demand_forecasts f
+        JOIN products p ON p.product_id = f.product_id
+        WHERE f.model_version = $1
+          AND f.forecast_date = (SELECT MIN(forecast_date) FROM demand_forecasts WHERE model_version = $1)
+        ORDER BY f.product_id
+        "#,
     )
+    .bind(run.id)
     .fetch_all(pool)
     .await?;
 
-    let mut sales_data: HashMap<i32, Vec<(NaiveDateTime, f32)>> = HashMap::new();
-    for row in rows {
-        if let (Some(quantity), Some(transaction_date)) = (row.quantity, row.transaction_date) {
-            sales_data
-                .entry(row.product_id)
-                .or_default()
-                .push((transaction_date, quantity));
-        }
-    }
-
-    Ok(sales_data)
-}
-
-async fn get_product_name(pool: &PgPool, product_id: i32) -> Result<Option<String>, sqlx::Error> {
-    let row = sqlx::query!("SELECT name FROM products WHERE product_id = $1", product_id)
-        .fetch_optional(pool)
-        .await?;
-    Ok(row.map(|r| r.name).flatten())
-
-
+    let query = AccuracyQuery {
+        product_id: None,
+        selected: Some(true),
+    };
+    let mut accuracy: HashMap<i32, MethodAccuracy> =
+        forecast_accuracy::get_forecast_accuracy(pool, &query)
+            .await?
+            .into_iter()
+            .map(|row| (row.product_id, row))
+            .collect();
+
+    let products = rows
+        .into_iter()
+        .map(|row| StockOptimization {
+            product_id: row.product_id,
+            product_name: row.name.unwrap_or_default(),
+            predicted_demand: row.units,
+            accuracy: accuracy
+                .remove(&row.product_id)
+                .filter(|accuracy| accuracy.method == row.method),
+            method: row.method,
+        })
+        .collect();
+
+    Ok(StockOptimizationReport {
+        age_minutes: Some(age_minutes),
+        forecast_run: Some(run),
+        products,
+    })
 }
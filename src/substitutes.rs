@@ -0,0 +1,236 @@
+use crate::errors::RequestError;
+use crate::ml::substitutes;
+use crate::recommendations;
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+const MAX_WINDOW_DAYS: i64 = 10 * 366;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubstituteOptions {
+    /// Highest lift that still counts as "bought instead of", e.g. 0.5.
+    pub max_lift: Option<f64>,
+    /// Co-occurrences a pair must be expected to have before its lift is trusted.
+    pub min_expected: Option<f64>,
+    /// Largest relative price difference, e.g. 0.3 for 30%.
+    pub price_tolerance: Option<f64>,
+    /// Days either side of a product's first sale compared for cannibalization.
+    pub window_days: Option<i64>,
+    /// Drop in the older product's daily sales that counts as cannibalization, e.g. 0.2.
+    pub min_cannibalization: Option<f64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Cannibalization {
+    pub introduced_product_id: i32,
+    pub introduced_on: NaiveDate,
+    pub affected_product_id: i32,
+    pub daily_receipts_before: f64,
+    pub daily_receipts_after: f64,
+    pub change_pct: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Substitute {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub selling_price: Option<f32>,
+    pub receipts: i64,
+    pub together: i64,
+    pub expected_together: f64,
+    pub lift: f64,
+    pub cannibalization: Option<Cannibalization>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubstituteReport {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub selling_price: Option<f32>,
+    pub substitutes: Vec<Substitute>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ProductRow {
+    product_id: i32,
+    name: Option<String>,
+    category: Option<String>,
+    selling_price: Option<f32>,
+}
+
+/// Products in the same category and price range that customers buy instead
+/// of `product_id`: rarely on the same receipt, or losing sales when the
+/// other one was introduced. `None` when the product does not exist.
+pub async fn get_substitutes(
+    pool: &PgPool,
+    product_id: i32,
+    options: &SubstituteOptions,
+) -> Result<Option<SubstituteReport>> {
+    let max_lift = options.max_lift.unwrap_or(0.5);
+    let min_expected = options.min_expected.unwrap_or(5.0);
+    let price_tolerance = options.price_tolerance.unwrap_or(0.3).max(0.0);
+    let window_days = options.window_days.unwrap_or(28).max(1);
+    if window_days > MAX_WINDOW_DAYS {
+        bail!(RequestError::Invalid(format!(
+            "window_days must be at most {}",
+            MAX_WINDOW_DAYS
+        )));
+    }
+    let min_cannibalization = options.min_cannibalization.unwrap_or(0.2);
+    let limit = options.limit.unwrap_or(10);
+
+    let Some(product) = sqlx::query_as::<_, ProductRow>(
+        "SELECT product_id, name, category, selling_price FROM products WHERE product_id = $1",
+    )
+    .bind(product_id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let candidates: Vec<ProductRow> = match product.category.as_deref() {
+        Some(category) if !category.trim().is_empty() => {
+            sqlx::query_as::<_, ProductRow>(
+                "SELECT product_id, name, category, selling_price FROM products
+                 WHERE category = $1 AND product_id <> $2",
+            )
+            .bind(category)
+            .bind(product_id)
+            .fetch_all(pool)
+            .await?
+        }
+        _ => Vec::new(),
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|c| similar_price(product.selling_price, c.selling_price, price_tolerance));
+
+    let baskets = recommendations::get_transactions(pool).await?;
+    let mut receipt_dates: HashMap<i32, Vec<NaiveDate>> = HashMap::new();
+    for basket in &baskets {
+        if let Some(date) = basket.transaction_date {
+            let mut items = basket.items.clone();
+            items.sort();
+            items.dedup();
+            for id in items {
+                receipt_dates.entry(id).or_default().push(date.date());
+            }
+        }
+    }
+    let dates = baskets.iter().filter_map(|b| b.transaction_date);
+    let history = dates.clone().min().zip(dates.max());
+
+    let transactions: Vec<Vec<i32>> = baskets.into_iter().map(|b| b.items).collect();
+    let lifts = substitutes::lifts_against(&transactions, &product_id);
+
+    let mut found: Vec<Substitute> = candidates
+        .filter_map(|candidate| {
+            let pair = lifts.get(&candidate.product_id)?;
+            let cannibalization = cannibalization(
+                product_id,
+                candidate.product_id,
+                &receipt_dates,
+                history,
+                window_days,
+            );
+            let negative = pair.expected >= min_expected && pair.lift <= max_lift;
+            let cannibalized = cannibalization
+                .as_ref()
+                .is_some_and(|c| c.change_pct <= -min_cannibalization * 100.0);
+            if !negative && !cannibalized {
+                return None;
+            }
+
+            Some(Substitute {
+                product_id: candidate.product_id,
+                name: candidate.name,
+                category: candidate.category,
+                selling_price: candidate.selling_price,
+                receipts: pair.support as i64,
+                together: pair.together as i64,
+                expected_together: pair.expected,
+                lift: pair.lift,
+                cannibalization,
+            })
+        })
+        .collect();
+
+    found.sort_by(|a, b| a.lift.partial_cmp(&b.lift).unwrap_or(std::cmp::Ordering::Equal));
+    found.truncate(limit);
+
+    Ok(Some(SubstituteReport {
+        product_id: product.product_id,
+        name: product.name,
+        category: product.category,
+        selling_price: product.selling_price,
+        substitutes: found,
+    }))
+}
+
+fn similar_price(a: Option<f32>, b: Option<f32>, tolerance: f64) -> bool {
+    match (a.filter(|p| *p > 0.0), b.filter(|p| *p > 0.0)) {
+        (Some(a), Some(b)) => ((a - b).abs() / a.max(b)) as f64 <= tolerance,
+        _ => true,
+    }
+}
+
+// Whichever of the two products first sold later is treated as introduced on
+// that day, and the other one's receipts per day are compared over the window
+// before and after. Products already selling when the history starts were not
+// introduced within it.
+fn cannibalization(
+    a: i32,
+    b: i32,
+    receipt_dates: &HashMap<i32, Vec<NaiveDate>>,
+    history: Option<(NaiveDateTime, NaiveDateTime)>,
+    window_days: i64,
+) -> Option<Cannibalization> {
+    let (history_start, history_end) = history?;
+    let first_sale = |id: i32| receipt_dates.get(&id)?.iter().min().copied();
+    let (first_a, first_b) = (first_sale(a)?, first_sale(b)?);
+    let (introduced, affected, introduced_on) = if first_a > first_b {
+        (a, b, first_a)
+    } else {
+        (b, a, first_b)
+    };
+
+    let window = Duration::days(window_days);
+    let window_start = introduced_on.checked_sub_signed(window)?;
+    if window_start < history_start.date() {
+        return None;
+    }
+    let window_end = introduced_on.checked_add_signed(window).unwrap_or(NaiveDate::MAX);
+    // A product introduced last week only has a week of "after" to compare.
+    let after_days = (history_end.date() - introduced_on).num_days().clamp(0, window_days - 1) + 1;
+
+    let dates = receipt_dates.get(&affected)?;
+    let before = dates
+        .iter()
+        .filter(|d| **d >= window_start && **d < introduced_on)
+        .count() as f64;
+    let after = dates
+        .iter()
+        .filter(|d| **d >= introduced_on && **d < window_end)
+        .count() as f64;
+    if before <= 0.0 {
+        return None;
+    }
+
+    let daily_receipts_before = before / window_days as f64;
+    let daily_receipts_after = after / after_days as f64;
+    Some(Cannibalization {
+        introduced_product_id: introduced,
+        introduced_on,
+        affected_product_id: affected,
+        daily_receipts_before,
+        daily_receipts_after,
+        change_pct: (daily_receipts_after / daily_receipts_before - 1.0) * 100.0,
+    })
+}
@@ -1,4 +1,6 @@
+use crate::stock_movements::{self, StockSnapshot};
 use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
 use db_models::{Customer, Receipt, Sale};
 use sqlx::{MySqlPool, PgPool};
 use std::collections::HashMap;
@@ -11,7 +13,20 @@ struct LogChange {
     primary_key_value: String,
 }
 
-pub async fn process_changes(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Result<()> {
+/// Tables whose pending changes were applied in one sync run.
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub tables: Vec<String>,
+}
+
+impl SyncSummary {
+    /// Whether receipts or sales changed, i.e. whether the recommendation counts are stale.
+    pub fn has_new_sales(&self) -> bool {
+        self.tables.iter().any(|t| t == "receipts" || t == "sales")
+    }
+}
+
+pub async fn process_changes(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Result<SyncSummary> {
     let changes = sqlx::query_as::<_, LogChange>(
         "SELECT id, table_name, primary_key_value FROM log_table_sync_change WHERE status = 'pending' ORDER BY change_time ASC",
     )
@@ -20,10 +35,11 @@ pub async fn process_changes(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Result
     .context("Failed to fetch pending changes from MySQL")?;
 
     if changes.is_empty() {
-        return Ok(());
+        return Ok(SyncSummary::default());
     }
 
     info!("Found {} new changes to process.", changes.len());
+    let mut summary = SyncSummary::default();
 
     let mut grouped_changes: HashMap<String, Vec<LogChange>> = HashMap::new();
     for change in changes {
@@ -73,10 +89,11 @@ pub async fn process_changes(mysql_pool: &MySqlPool, pg_pool: &PgPool) -> Result
                     .await
                     .context("Failed to update sync status in MySQL")?;
             }
+            summary.tables.push(table_name);
         }
     }
 
-    Ok(())
+    Ok(summary)
 }
 
 async fn apply_customer_changes(
@@ -133,6 +150,19 @@ struct MySqlProduct {
     #[sqlx(rename = "sellingprice")]
     selling_price: String,
     current_stock: String,
+    restock_level: i32,
+    #[sqlx(rename = "cost_price_excl")]
+    cost_price: String,
+    unit: String,
+    restock_quantity: i32,
+    supplier: String,
+    inventory_type: String,
+    used_stock: String,
+    additional_stock: String,
+    shift_stock_fed: String,
+    shift_current_stock: String,
+    stock_lastly_adjusted_on: Option<NaiveDateTime>,
+    last_updated: Option<NaiveDateTime>,
 }
 
 async fn apply_product_changes(
@@ -144,7 +174,10 @@ async fn apply_product_changes(
 
     let product_pks: Vec<&str> = changes.iter().map(|c| c.primary_key_value.as_str()).collect();
     let query_str = format!(
-        "SELECT product_id, product_code, productname, department, category, sellingprice, current_stock FROM products WHERE product_id IN ({})",
+        "SELECT product_id, product_code, productname, department, category, sellingprice, current_stock, restock_level, cost_price_excl, unit, restock_quantity, supplier, inventory_type, used_stock, additional_stock, shift_stock_fed, shift_current_stock,
+            IF(stock_lastly_adjusted_on > '1000-01-01', stock_lastly_adjusted_on, NULL) AS stock_lastly_adjusted_on,
+            IF(last_updated > '1000-01-01', last_updated, NULL) AS last_updated
+         FROM products WHERE product_id IN ({})",
         product_pks.join(",")
     );
 
@@ -153,20 +186,37 @@ async fn apply_product_changes(
         .await
         .context("Failed to fetch products from MySQL")?;
 
+    let parse_stock = |value: &str| value.trim().parse::<f64>().unwrap_or(0.0);
     for p in products {
         let selling_price = p.selling_price.trim().parse::<f32>().unwrap_or(0.0);
         let current_stock = p.current_stock.trim().parse::<f32>().unwrap_or(0.0);
+        let snapshot = StockSnapshot {
+            product_id: p.product_id,
+            current_stock: parse_stock(&p.current_stock),
+            used_stock: parse_stock(&p.used_stock),
+            additional_stock: parse_stock(&p.additional_stock),
+            shift_stock_fed: parse_stock(&p.shift_stock_fed),
+            shift_current_stock: parse_stock(&p.shift_current_stock),
+            adjusted_at: p.stock_lastly_adjusted_on,
+            updated_at: p.last_updated,
+        };
 
         sqlx::query(
-            "INSERT INTO products (product_id, product_code, name, department, category, selling_price, current_stock)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "INSERT INTO products (product_id, product_code, name, department, category, selling_price, current_stock, restock_level, cost_price, unit, restock_quantity, supplier, inventory_type)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
              ON CONFLICT (product_id) DO UPDATE SET
                 product_code = EXCLUDED.product_code,
                 name = EXCLUDED.name,
                 department = EXCLUDED.department,
                 category = EXCLUDED.category,
                 selling_price = EXCLUDED.selling_price,
-                current_stock = EXCLUDED.current_stock",
+                current_stock = EXCLUDED.current_stock,
+                restock_level = EXCLUDED.restock_level,
+                cost_price = EXCLUDED.cost_price,
+                unit = EXCLUDED.unit,
+                restock_quantity = EXCLUDED.restock_quantity,
+                supplier = EXCLUDED.supplier,
+                inventory_type = EXCLUDED.inventory_type",
         )
         .bind(p.product_id)
         .bind(p.product_code)
@@ -175,9 +225,19 @@ async fn apply_product_changes(
         .bind(p.category)
         .bind(selling_price)
         .bind(current_stock)
+        .bind(p.restock_level as f32)
+        .bind(p.cost_price.trim().parse::<f32>().unwrap_or(0.0))
+        .bind(p.unit)
+        .bind(p.restock_quantity as f32)
+        .bind(p.supplier)
+        .bind(p.inventory_type)
         .execute(pg_pool)
         .await
         .context("Failed to upsert product to Postgres")?;
+
+        stock_movements::record_snapshot(pg_pool, &snapshot)
+            .await
+            .context("Failed to record stock movements")?;
     }
     Ok(())
 }
@@ -0,0 +1,251 @@
+use crate::errors::RequestError;
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Deserialize)]
+pub struct ShelfLife {
+    /// Days a product keeps once received; `None` marks it as not perishable.
+    pub shelf_life_days: Option<f64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PerishableProduct {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub shelf_life_days: f32,
+    pub current_stock: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewWaste {
+    pub product_id: i32,
+    pub quantity: f64,
+    /// Defaults to `expired`.
+    pub reason: Option<String>,
+    /// Defaults to today.
+    pub wasted_on: Option<NaiveDate>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct WasteRecord {
+    pub id: i32,
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub quantity: f64,
+    pub reason: String,
+    pub unit_cost: Option<f64>,
+    pub cost: Option<f64>,
+    pub wasted_on: NaiveDate,
+    pub notes: Option<String>,
+    pub recorded_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WasteQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub product_id: Option<i32>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ProductWaste {
+    pub product_id: i32,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub quantity: f64,
+    pub cost: f64,
+    pub records: i64,
+    /// Units sold over the same days.
+    pub sold: f64,
+    /// Waste as a share of units wasted plus sold, in percent.
+    pub waste_pct: Option<f64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DailyWaste {
+    pub date: NaiveDate,
+    pub cost: f64,
+    pub records: i64,
+    pub products: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WasteReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_cost: f64,
+    pub products: Vec<ProductWaste>,
+    pub days: Vec<DailyWaste>,
+}
+
+/// Sets or clears a product's shelf life. `false` when the product does not exist.
+pub async fn set_shelf_life(pool: &PgPool, product_id: i32, shelf_life: &ShelfLife) -> Result<bool> {
+    if shelf_life.shelf_life_days.is_some_and(|days| days <= 0.0) {
+        bail!(RequestError::Invalid("shelf_life_days must be positive".to_string()));
+    }
+    let result = sqlx::query("UPDATE products SET shelf_life_days = $2 WHERE product_id = $1")
+        .bind(product_id)
+        .bind(shelf_life.shelf_life_days.map(|days| days as f32))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_perishables(pool: &PgPool) -> Result<Vec<PerishableProduct>, sqlx::Error> {
+    sqlx::query_as::<_, PerishableProduct>(
+        "SELECT product_id, name, unit, shelf_life_days, current_stock
+         FROM products
+         WHERE shelf_life_days IS NOT NULL
+         ORDER BY shelf_life_days, product_id",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Records waste at the product's current cost price.
+pub async fn record_waste(pool: &PgPool, waste: &NewWaste) -> Result<WasteRecord> {
+    if waste.quantity <= 0.0 {
+        bail!(RequestError::Invalid("quantity must be positive".to_string()));
+    }
+    let reason = waste
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty())
+        .unwrap_or("expired")
+        .to_lowercase();
+
+    let record = sqlx::query_as::<_, WasteRecord>(
+        r#"
+        WITH inserted AS (
+            INSERT INTO waste_records (product_id, quantity, reason, unit_cost, wasted_on, notes)
+            SELECT product_id, $2, $3, NULLIF(cost_price, 0), COALESCE($4, CURRENT_DATE), $5
+            FROM products
+            WHERE product_id = $1
+            RETURNING *
+        )
+        SELECT w.id, w.product_id, p.name, w.quantity, w.reason, w.unit_cost,
+               w.quantity * w.unit_cost AS cost, w.wasted_on, w.notes, w.recorded_at
+        FROM inserted w
+        JOIN products p ON p.product_id = w.product_id
+        "#,
+    )
+    .bind(waste.product_id)
+    .bind(waste.quantity)
+    .bind(&reason)
+    .bind(waste.wasted_on)
+    .bind(&waste.notes)
+    .fetch_optional(pool)
+    .await?;
+    match record {
+        Some(record) => Ok(record),
+        None => bail!(RequestError::NotFound(format!(
+            "Product {} does not exist",
+            waste.product_id
+        ))),
+    }
+}
+
+pub async fn get_waste(pool: &PgPool, query: &WasteQuery) -> Result<Vec<WasteRecord>, sqlx::Error> {
+    sqlx::query_as::<_, WasteRecord>(
+        r#"
+        SELECT w.id, w.product_id, p.name, w.quantity, w.reason, w.unit_cost,
+               w.quantity * w.unit_cost AS cost, w.wasted_on, w.notes, w.recorded_at
+        FROM waste_records w
+        JOIN products p ON p.product_id = w.product_id
+        WHERE ($1::DATE IS NULL OR w.wasted_on >= $1)
+          AND ($2::DATE IS NULL OR w.wasted_on <= $2)
+          AND ($3::INTEGER IS NULL OR w.product_id = $3)
+          AND ($4::VARCHAR IS NULL OR w.reason = $4)
+        ORDER BY w.wasted_on DESC, w.id DESC
+        "#,
+    )
+    .bind(query.from)
+    .bind(query.to)
+    .bind(query.product_id)
+    .bind(query.reason.as_deref().map(str::to_lowercase))
+    .fetch_all(pool)
+    .await
+}
+
+/// Waste cost by product and by day over a period, 30 days up to today by
+/// default. Waste without a cost price counts towards quantities only.
+pub async fn get_waste_report(pool: &PgPool, query: &WasteQuery) -> Result<WasteReport> {
+    let to = query.to.unwrap_or_else(|| chrono::Local::now().date_naive());
+    let from = match query.from {
+        Some(from) => from,
+        None => to.checked_sub_signed(Duration::days(29)).ok_or_else(|| {
+            RequestError::Invalid("to is too early to default from; pass from as well".to_string())
+        })?,
+    };
+    if to < from {
+        bail!(RequestError::Invalid("to must not be before from".to_string()));
+    }
+
+    let products = sqlx::query_as::<_, ProductWaste>(
+        r#"
+        WITH wasted AS (
+            SELECT product_id, SUM(quantity) AS quantity,
+                   COALESCE(SUM(quantity * unit_cost), 0) AS cost, COUNT(*) AS records
+            FROM waste_records
+            WHERE wasted_on BETWEEN $1 AND $2
+              AND ($3::INTEGER IS NULL OR product_id = $3)
+              AND ($4::VARCHAR IS NULL OR reason = $4)
+            GROUP BY product_id
+        ),
+        sold AS (
+            SELECT s.product_id, SUM(s.quantity)::DOUBLE PRECISION AS sold
+            FROM sales s
+            JOIN receipts r ON r.receipt_id = s.receipt_id
+            WHERE r.transaction_date::DATE BETWEEN $1 AND $2
+              AND s.product_id IN (SELECT product_id FROM wasted)
+            GROUP BY s.product_id
+        )
+        SELECT w.product_id, p.name, p.unit, w.quantity, w.cost, w.records,
+               COALESCE(s.sold, 0) AS sold,
+               100 * w.quantity / NULLIF(w.quantity + COALESCE(s.sold, 0), 0) AS waste_pct
+        FROM wasted w
+        JOIN products p ON p.product_id = w.product_id
+        LEFT JOIN sold s ON s.product_id = w.product_id
+        ORDER BY w.cost DESC, w.quantity DESC
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(query.product_id)
+    .bind(query.reason.as_deref().map(str::to_lowercase))
+    .fetch_all(pool)
+    .await?;
+
+    let days = sqlx::query_as::<_, DailyWaste>(
+        r#"
+        SELECT wasted_on AS date, COALESCE(SUM(quantity * unit_cost), 0) AS cost,
+               COUNT(*) AS records, COUNT(DISTINCT product_id) AS products
+        FROM waste_records
+        WHERE wasted_on BETWEEN $1 AND $2
+          AND ($3::INTEGER IS NULL OR product_id = $3)
+          AND ($4::VARCHAR IS NULL OR reason = $4)
+        GROUP BY wasted_on
+        ORDER BY wasted_on
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(query.product_id)
+    .bind(query.reason.as_deref().map(str::to_lowercase))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(WasteReport {
+        from,
+        to,
+        total_cost: products.iter().map(|p| p.cost).sum(),
+        products,
+        days,
+    })
+}